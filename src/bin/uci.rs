@@ -0,0 +1,37 @@
+use chess_engine::chess::Board;
+use chess_engine::{eval, perft, uci};
+
+// Speaks the UCI protocol on stdin/stdout, for chess GUIs. `perft <depth>`
+// and `eval` instead print the move counts or the static evaluation of the
+// start position, or of `--fen "<FEN>"`, and exit
+fn main() {
+    let args: Vec<String> = std::env::args().collect();
+    let command = args.get(1).map(String::as_str);
+    if !matches!(command, Some("perft" | "eval")) {
+        uci::run();
+        return;
+    }
+
+    let mut board = match args.iter().position(|arg| arg == "--fen") {
+        Some(index) => match Board::from_fen(args.get(index + 1).map_or("", String::as_str)) {
+            Ok(board) => board,
+            Err(err) => {
+                eprintln!("Invalid FEN: {}", err);
+                std::process::exit(1);
+            }
+        },
+        None => Board::new(),
+    };
+
+    if command == Some("eval") {
+        println!("{}", eval::evaluate_terms(&board));
+        return;
+    }
+    match args.get(2).and_then(|depth| depth.parse().ok()) {
+        Some(depth) => perft::run(&mut board, depth),
+        None => {
+            eprintln!("Usage: uci perft <depth> [--fen \"<FEN>\"]");
+            std::process::exit(1);
+        }
+    }
+}
@@ -1,6 +1,23 @@
-extern crate sdl2;
+use std::fmt;
 
-#[derive(Copy, Clone, PartialEq)]
+use crate::bitboard::{
+    bishop_attacks, bit, bits, index_square, queen_attacks, rook_attacks, square_index, Bitboard,
+    KING_ATTACKS, KNIGHT_ATTACKS, PAWN_ATTACKS,
+};
+use crate::zobrist;
+
+/// Pieces a pawn may promote to, in the order they are offered.
+pub const PROMOTION_PIECES: [PieceType; 4] = [
+    PieceType::Queen,
+    PieceType::Knight,
+    PieceType::Rook,
+    PieceType::Bishop,
+];
+
+// Squares of the same color as a1
+const DARK_SQUARES: Bitboard = 0xAA55_AA55_AA55_AA55;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
 pub struct Piece {
     pub p_type: PieceType,
     pub p_color: PColor,
@@ -21,7 +38,7 @@ impl Piece {
         }
     }
 }
-#[derive(Copy, Clone, Debug, PartialEq)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
 pub enum PieceType {
     Pawn,
     Rook,
@@ -31,54 +48,274 @@ pub enum PieceType {
     King,
 }
 
-#[derive(Copy, Clone, PartialEq, Debug)]
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
 pub enum PColor {
     White,
     Black,
 }
 
+impl PColor {
+    pub fn opposite(self) -> PColor {
+        match self {
+            PColor::White => PColor::Black,
+            PColor::Black => PColor::White,
+        }
+    }
+
+    // Rank the pieces of this color start on
+    fn back_rank(self) -> usize {
+        match self {
+            PColor::White => 0,
+            PColor::Black => 7,
+        }
+    }
+}
+
+/// Which castling moves are still allowed. A right is lost once the king
+/// or the matching rook has moved, or the rook has been captured.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct CastlingRights {
+    pub white_kingside: bool,
+    pub white_queenside: bool,
+    pub black_kingside: bool,
+    pub black_queenside: bool,
+}
+
+impl CastlingRights {
+    pub fn all() -> CastlingRights {
+        CastlingRights {
+            white_kingside: true,
+            white_queenside: true,
+            black_kingside: true,
+            black_queenside: true,
+        }
+    }
+
+    pub fn none() -> CastlingRights {
+        CastlingRights {
+            white_kingside: false,
+            white_queenside: false,
+            black_kingside: false,
+            black_queenside: false,
+        }
+    }
+
+    pub fn kingside(&self, color: PColor) -> bool {
+        match color {
+            PColor::White => self.white_kingside,
+            PColor::Black => self.black_kingside,
+        }
+    }
+
+    pub fn queenside(&self, color: PColor) -> bool {
+        match color {
+            PColor::White => self.white_queenside,
+            PColor::Black => self.black_queenside,
+        }
+    }
+
+    // Drops the rights that depend on a king or rook standing on `square`
+    fn remove_for_square(&mut self, square: (usize, usize)) {
+        match square {
+            (0, 4) => {
+                self.white_kingside = false;
+                self.white_queenside = false;
+            }
+            (7, 4) => {
+                self.black_kingside = false;
+                self.black_queenside = false;
+            }
+            (0, 7) => self.white_kingside = false,
+            (0, 0) => self.white_queenside = false,
+            (7, 7) => self.black_kingside = false,
+            (7, 0) => self.black_queenside = false,
+            _ => {}
+        }
+    }
+}
+
+/// Name of a `(rank, file)` square in algebraic notation, e.g. `"e4"`.
+pub fn square_name(square: (usize, usize)) -> String {
+    format!("{}{}", (b'a' + square.1 as u8) as char, square.0 + 1)
+}
+
+/// Parses an algebraic square name such as `"e4"` into `(rank, file)`.
+pub fn parse_square(name: &str) -> Option<(usize, usize)> {
+    match name.as_bytes() {
+        &[file @ b'a'..=b'h', rank @ b'1'..=b'8'] => {
+            Some(((rank - b'1') as usize, (file - b'a') as usize))
+        }
+        _ => None,
+    }
+}
+
+/// Special kinds of move that need more than moving a single piece.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum MoveFlag {
+    Normal,
+    DoublePush,
+    EnPassant,
+    Castling,
+}
+
+/// A move as a value, with squares given as `(rank, file)`.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Move {
+    pub from: (usize, usize),
+    pub to: (usize, usize),
+    pub piece: PieceType,
+    pub captured: Option<PieceType>,
+    pub promotion: Option<PieceType>,
+    pub flag: MoveFlag,
+}
+
+// Coordinate notation as used by UCI, e.g. "e2e4" or "e7e8q"
+impl fmt::Display for Move {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}{}", square_name(self.from), square_name(self.to))?;
+        if let Some(promotion) = self.promotion {
+            let letter = match promotion {
+                PieceType::Queen => 'q',
+                PieceType::Rook => 'r',
+                PieceType::Bishop => 'b',
+                PieceType::Knight => 'n',
+                PieceType::Pawn | PieceType::King => '?',
+            };
+            write!(f, "{}", letter)?;
+        }
+        Ok(())
+    }
+}
+
+/// A move that is not legal in the position it was played in.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct IllegalMove(pub Move);
+
+impl fmt::Display for IllegalMove {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} is not a legal move here", self.0)
+    }
+}
+
+impl std::error::Error for IllegalMove {}
+
+/// State of the game as seen from the current position.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum GameStatus {
+    Ongoing,
+    /// The color that delivered mate.
+    Checkmate(PColor),
+    Stalemate,
+    Draw(DrawReason),
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum DrawReason {
+    FiftyMoveRule,
+    ThreefoldRepetition,
+    InsufficientMaterial,
+}
+
+impl GameStatus {
+    pub fn is_over(&self) -> bool {
+        *self != GameStatus::Ongoing
+    }
+}
+
+impl fmt::Display for GameStatus {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            GameStatus::Ongoing => write!(f, "Game in progress"),
+            GameStatus::Checkmate(winner) => write!(f, "Checkmate, {:?} wins", winner),
+            GameStatus::Stalemate => write!(f, "Draw by stalemate"),
+            GameStatus::Draw(DrawReason::FiftyMoveRule) => write!(f, "Draw by the fifty-move rule"),
+            GameStatus::Draw(DrawReason::ThreefoldRepetition) => {
+                write!(f, "Draw by threefold repetition")
+            }
+            GameStatus::Draw(DrawReason::InsufficientMaterial) => {
+                write!(f, "Draw by insufficient material")
+            }
+        }
+    }
+}
+
+/// A played move together with the state it overwrote, so that
+/// `Board::unmake_move` can restore the position exactly.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct HistoryEntry {
+    pub mv: Move,
+    pub castling_rights: CastlingRights,
+    pub en_passant: Option<(usize, usize)>,
+    pub halfmove_clock: u32,
+}
+
+#[derive(Clone)]
 pub struct Board {
-    pub squares: [[Option<Piece>; 8]; 8],
-    pub selected_piece: Option<(usize, usize)>,
-    pub turn: PColor,
-    pub king_under_attack: [bool; 2],
+    // Bitboards per piece type and per color, indexed by `PieceType as usize`
+    // and `PColor as usize`
+    pieces: [Bitboard; 6],
+    colors: [Bitboard; 2],
+    // The piece on each square, mirroring the bitboards for quick lookups
+    mailbox: [Option<Piece>; 64],
+    // Side to move, castling rights and en passant square are part of the
+    // hash, so only moves and FEN parsing may change them
+    pub(crate) turn: PColor,
+    pub(crate) castling_rights: CastlingRights,
+    // Square a pawn skipped with a double push on the previous move
+    pub(crate) en_passant: Option<(usize, usize)>,
+    /// Half moves since the last capture or pawn move, for the fifty-move rule.
+    pub halfmove_clock: u32,
+    /// Starts at 1 and goes up after every move by Black.
+    pub fullmove_number: u32,
+    // Zobrist hash of the position, kept up to date by every change
+    hash: u64,
+    // Hashes of every position reached so far, the current one last
+    position_history: Vec<u64>,
+    // Undo information for every move made, the last move last
+    history: Vec<HistoryEntry>,
 }
 
-fn print_board(board: &Board) {
-    for rank in (0..8).rev() {
-        for file in 0..8 {
-            match board.squares[rank][file] {
-                Some(piece) => {
-                    let piece_symbol = match piece.p_type {
-                        PieceType::Pawn => "♙",
-                        PieceType::Rook => "♖",
-                        PieceType::Knight => "♘",
-                        PieceType::Bishop => "♗",
-                        PieceType::Queen => "♕",
-                        PieceType::King => "♔",
-                    };
-                    let color_symbol = match piece.p_color {
-                        PColor::White => "W",
-                        PColor::Black => "B",
-                    };
-                    print!("{}{} ", piece_symbol, color_symbol);
-                }
-                None => {
-                    print!(".  ");
+impl fmt::Display for Board {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for rank in (0..8).rev() {
+            for file in 0..8 {
+                match self.piece_at((rank, file)) {
+                    Some(piece) => {
+                        let piece_symbol = match piece.p_type {
+                            PieceType::Pawn => "♙",
+                            PieceType::Rook => "♖",
+                            PieceType::Knight => "♘",
+                            PieceType::Bishop => "♗",
+                            PieceType::Queen => "♕",
+                            PieceType::King => "♔",
+                        };
+                        let color_symbol = match piece.p_color {
+                            PColor::White => "W",
+                            PColor::Black => "B",
+                        };
+                        write!(f, "{}{} ", piece_symbol, color_symbol)?;
+                    }
+                    None => {
+                        write!(f, ".  ")?;
+                    }
                 }
             }
+            writeln!(f)?; // Start a new line for the next rank
         }
-        println!(); // Start a new line for the next rank
+        Ok(())
+    }
+}
+
+impl Default for Board {
+    fn default() -> Self {
+        Self::new()
     }
 }
+
 impl Board {
     pub fn new() -> Board {
-        let mut board = Board {
-            squares: [[None; 8]; 8],
-            selected_piece: None,
-            turn: PColor::White,
-            king_under_attack: [false; 2],
-        };
+        let mut board = Board::empty();
+        board.castling_rights = CastlingRights::all();
 
         //Starting position
         //Set up white pieces
@@ -108,327 +345,556 @@ impl Board {
         board.new_piece(Piece::new_black(PieceType::Queen), 7, 3);
         board.new_piece(Piece::new_black(PieceType::King), 7, 4);
 
+        board.reset_history();
         board
     }
 
-    pub fn move_generation(&mut self) -> Vec<(usize, usize)> {
-        let mut moves = Vec::new();
+    /// A board without any pieces, White to move and no castling rights.
+    pub fn empty() -> Board {
+        Board {
+            pieces: [0; 6],
+            colors: [0; 2],
+            mailbox: [None; 64],
+            turn: PColor::White,
+            castling_rights: CastlingRights::none(),
+            en_passant: None,
+            halfmove_clock: 0,
+            fullmove_number: 1,
+            hash: 0,
+            position_history: Vec::new(),
+            history: Vec::new(),
+        }
+    }
 
-        match self.selected_piece {
-            Some((rank, file)) => {
-                let piece = self.squares[rank][file].unwrap();
-                match piece.p_type {
-                    PieceType::Pawn => {
-                        // Generate pawn moves
-                        let direction = match piece.p_color {
-                            PColor::White => 1,
-                            PColor::Black => -1,
-                        };
-                        let new_rank = rank as i32 + direction;
-                        if new_rank >= 0 && new_rank < 8 {
-                            // Move forward
-                            if self.squares[new_rank as usize][file].is_none() {
-                                moves.push((new_rank as usize, file));
-                            }
-                            // Move forward two squares
-                            if (rank == 1 && direction == 1) || (rank == 6 && direction == -1) {
-                                let new_rank = rank as i32 + 2 * direction;
-                                if self.squares[new_rank as usize][file].is_none()
-                                    && self.squares[(new_rank - direction) as usize][file].is_none()
-                                {
-                                    moves.push((new_rank as usize, file));
-                                }
-                            }
-                            // Capture diagonally to the left
-                            if file > 0 {
-                                if let Some(captured_piece) =
-                                    self.squares[new_rank as usize][file - 1]
-                                {
-                                    if captured_piece.p_color != piece.p_color {
-                                        moves.push((new_rank as usize, file - 1));
-                                    }
-                                }
-                            }
-                            // Capture diagonally to the right
-                            if file < 7 {
-                                if let Some(captured_piece) =
-                                    self.squares[new_rank as usize][file + 1]
-                                {
-                                    if captured_piece.p_color != piece.p_color {
-                                        moves.push((new_rank as usize, file + 1));
-                                    }
-                                }
-                            }
-
-                            //en passant for black and white
-                            if (rank == 4 && direction == 1) || (rank == 3 && direction == -1) {
-                                if file > 0 {
-                                    if let Some(captured_piece) = self.squares[rank][file - 1] {
-                                        if captured_piece.p_color != piece.p_color
-                                            && captured_piece.p_type == PieceType::Pawn
-                                        {
-                                            moves.push((new_rank as usize, file - 1));
-                                        }
-                                    }
-                                }
-                                if file < 7 {
-                                    if let Some(captured_piece) = self.squares[rank][file + 1] {
-                                        if captured_piece.p_color != piece.p_color
-                                            && captured_piece.p_type == PieceType::Pawn
-                                        {
-                                            moves.push((new_rank as usize, file + 1));
-                                        }
-                                    }
-                                }
-                            }
-                        }
-                    }
-                    PieceType::Rook => {
-                        // Generate rook moves
-                        for direction in &[(1, 0), (-1, 0), (0, 1), (0, -1)] {
-                            let mut new_rank = rank as i32 + direction.0;
-                            let mut new_file = file as i32 + direction.1;
-                            while new_rank >= 0 && new_rank < 8 && new_file >= 0 && new_file < 8 {
-                                if self.squares[new_rank as usize][new_file as usize].is_none() {
-                                    moves.push((new_rank as usize, new_file as usize));
-                                } else {
-                                    if let Some(captured_piece) =
-                                        self.squares[new_rank as usize][new_file as usize]
-                                    {
-                                        if captured_piece.p_color != piece.p_color {
-                                            moves.push((new_rank as usize, new_file as usize));
-                                        }
-                                    }
-                                    break;
-                                }
-                                new_rank += direction.0;
-                                new_file += direction.1;
-                            }
-                        }
-                    }
+    // Makes the current position the first one of the game, and hashes it
+    // from scratch as the fields may have been set directly
+    pub(crate) fn reset_history(&mut self) {
+        self.hash = self.compute_hash();
+        self.position_history = vec![self.hash];
+        self.history.clear();
+    }
 
-                    PieceType::Knight => {
-                        // Generate knight moves
-                        for direction in &[
-                            (1, 2),
-                            (1, -2),
-                            (-1, 2),
-                            (-1, -2),
-                            (2, 1),
-                            (2, -1),
-                            (-2, 1),
-                            (-2, -1),
-                        ] {
-                            let new_rank = rank as i32 + direction.0;
-                            let new_file = file as i32 + direction.1;
-                            if new_rank >= 0 && new_rank < 8 && new_file >= 0 && new_file < 8 {
-                                if self.squares[new_rank as usize][new_file as usize].is_none() {
-                                    moves.push((new_rank as usize, new_file as usize));
-                                } else {
-                                    if let Some(captured_piece) =
-                                        self.squares[new_rank as usize][new_file as usize]
-                                    {
-                                        if captured_piece.p_color != piece.p_color {
-                                            moves.push((new_rank as usize, new_file as usize));
-                                        }
-                                    }
-                                }
-                            }
-                        }
-                    }
+    /// Generates every pseudo-legal move for `color`. Moves that leave the
+    /// own king in check are only filtered out by `legal_moves`.
+    pub fn generate_moves(&self, color: PColor) -> Vec<Move> {
+        let mut moves = Vec::with_capacity(64);
+        let own = self.color_bb(color);
+        let occupied = self.occupied();
 
-                    PieceType::King => {
-                        // Generate king moves
-                        for direction in &[
-                            (1, 0),
-                            (-1, 0),
-                            (0, 1),
-                            (0, -1),
-                            (1, 1),
-                            (1, -1),
-                            (-1, 1),
-                            (-1, -1),
-                        ] {
-                            let new_rank = rank as i32 + direction.0;
-                            let new_file = file as i32 + direction.1;
-                            if new_rank >= 0 && new_rank < 8 && new_file >= 0 && new_file < 8 {
-                                if self.squares[new_rank as usize][new_file as usize].is_none() {
-                                    moves.push((new_rank as usize, new_file as usize));
-                                } else {
-                                    if let Some(captured_piece) =
-                                        self.squares[new_rank as usize][new_file as usize]
-                                    {
-                                        if captured_piece.p_color != piece.p_color {
-                                            moves.push((new_rank as usize, new_file as usize));
-                                        }
-                                    }
-                                }
-                            }
-                        }
-                    }
+        self.pawn_moves(color, &mut moves);
+        for from in bits(self.piece_bb(PieceType::Knight, color)) {
+            self.push_targets(from, KNIGHT_ATTACKS[from] & !own, &mut moves);
+        }
+        for from in bits(self.piece_bb(PieceType::Bishop, color)) {
+            self.push_targets(from, bishop_attacks(from, occupied) & !own, &mut moves);
+        }
+        for from in bits(self.piece_bb(PieceType::Rook, color)) {
+            self.push_targets(from, rook_attacks(from, occupied) & !own, &mut moves);
+        }
+        for from in bits(self.piece_bb(PieceType::Queen, color)) {
+            self.push_targets(from, queen_attacks(from, occupied) & !own, &mut moves);
+        }
+        for from in bits(self.piece_bb(PieceType::King, color)) {
+            self.push_targets(from, KING_ATTACKS[from] & !own, &mut moves);
+            self.castling_moves(index_square(from), color, &mut moves);
+        }
+        moves
+    }
 
-                    PieceType::Bishop => {
-                        // Generate bishop moves
-                        for direction in &[(1, 1), (1, -1), (-1, 1), (-1, -1)] {
-                            let mut new_rank = rank as i32 + direction.0;
-                            let mut new_file = file as i32 + direction.1;
-                            while new_rank >= 0 && new_rank < 8 && new_file >= 0 && new_file < 8 {
-                                if self.squares[new_rank as usize][new_file as usize].is_none() {
-                                    moves.push((new_rank as usize, new_file as usize));
-                                } else {
-                                    if let Some(captured_piece) =
-                                        self.squares[new_rank as usize][new_file as usize]
-                                    {
-                                        if captured_piece.p_color != piece.p_color {
-                                            moves.push((new_rank as usize, new_file as usize));
-                                        }
-                                    }
-                                    break;
-                                }
-                                new_rank += direction.0;
-                                new_file += direction.1;
-                            }
-                        }
-                    }
+    fn pawn_moves(&self, color: PColor, moves: &mut Vec<Move>) {
+        let empty = !self.occupied();
+        let enemy = self.color_bb(color.opposite());
+        let en_passant = self.en_passant.map_or(0, bit);
+        let (direction, start_rank): (i32, usize) = match color {
+            PColor::White => (8, 1),
+            PColor::Black => (-8, 6),
+        };
 
-                    PieceType::Queen => {
-                        // Generate queen moves
-                        for direction in &[
-                            (1, 0),
-                            (-1, 0),
-                            (0, 1),
-                            (0, -1),
-                            (1, 1),
-                            (1, -1),
-                            (-1, 1),
-                            (-1, -1),
-                        ] {
-                            let mut new_rank = rank as i32 + direction.0;
-                            let mut new_file = file as i32 + direction.1;
-                            while new_rank >= 0 && new_rank < 8 && new_file >= 0 && new_file < 8 {
-                                if self.squares[new_rank as usize][new_file as usize].is_none() {
-                                    moves.push((new_rank as usize, new_file as usize));
-                                } else {
-                                    if let Some(captured_piece) =
-                                        self.squares[new_rank as usize][new_file as usize]
-                                    {
-                                        if captured_piece.p_color != piece.p_color {
-                                            moves.push((new_rank as usize, new_file as usize));
-                                        }
-                                    }
-                                    break;
-                                }
-                                new_rank += direction.0;
-                                new_file += direction.1;
-                            }
-                        }
-                    }
+        for from in bits(self.piece_bb(PieceType::Pawn, color)) {
+            // Pawns never stand on the first or last rank, `from_fen` refuses
+            // them, but a board set up with `new_piece` could still have one
+            if !(8..56).contains(&from) {
+                continue;
+            }
+            // Move forward, and two squares from the starting rank
+            let forward = (from as i32 + direction) as usize;
+            if empty & (1 << forward) != 0 {
+                self.push_pawn_move(moves, from, forward);
+                let double = (forward as i32 + direction) as usize;
+                if from / 8 == start_rank && empty & (1 << double) != 0 {
+                    self.push_move(moves, from, double, MoveFlag::DoublePush);
                 }
             }
-            None => {
-                return moves;
+            // Capture diagonally
+            for to in bits(PAWN_ATTACKS[color as usize][from] & enemy) {
+                self.push_pawn_move(moves, from, to);
+            }
+            //en passant, only onto the square skipped by the last double push
+            if PAWN_ATTACKS[color as usize][from] & en_passant != 0 {
+                moves.push(Move {
+                    from: index_square(from),
+                    to: self.en_passant.unwrap(),
+                    piece: PieceType::Pawn,
+                    captured: Some(PieceType::Pawn),
+                    promotion: None,
+                    flag: MoveFlag::EnPassant,
+                });
             }
         }
+    }
 
-        moves
+    fn castling_moves(&self, from: (usize, usize), color: PColor, moves: &mut Vec<Move>) {
+        let rank = color.back_rank();
+        if from != (rank, 4) {
+            return;
+        }
+        let rooks = self.piece_bb(PieceType::Rook, color);
+        let enemy = color.opposite();
+        let empty = |files: &[usize]| files.iter().all(|&f| self.piece_at((rank, f)).is_none());
+        let safe = |files: &[usize]| {
+            files
+                .iter()
+                .all(|&f| !self.is_square_attacked((rank, f), enemy))
+        };
+
+        // The king may not castle out of, through or into check
+        if self.castling_rights.kingside(color)
+            && rooks & bit((rank, 7)) != 0
+            && empty(&[5, 6])
+            && safe(&[4, 5, 6])
+        {
+            self.push_move(
+                moves,
+                square_index(from),
+                square_index((rank, 6)),
+                MoveFlag::Castling,
+            );
+        }
+        if self.castling_rights.queenside(color)
+            && rooks & bit((rank, 0)) != 0
+            && empty(&[1, 2, 3])
+            && safe(&[4, 3, 2])
+        {
+            self.push_move(
+                moves,
+                square_index(from),
+                square_index((rank, 2)),
+                MoveFlag::Castling,
+            );
+        }
+    }
+
+    /// Whether any piece of color `by` attacks `square`. Works backwards
+    /// from the square with the attack tables, so no moves are generated.
+    pub fn is_square_attacked(&self, square: (usize, usize), by: PColor) -> bool {
+        let index = square_index(square);
+        let occupied = self.occupied();
+        let queens = self.piece_bb(PieceType::Queen, by);
+
+        // A pawn of `by` attacks the square if a pawn of the other color
+        // standing on it would attack the pawn
+        PAWN_ATTACKS[by.opposite() as usize][index] & self.piece_bb(PieceType::Pawn, by) != 0
+            || KNIGHT_ATTACKS[index] & self.piece_bb(PieceType::Knight, by) != 0
+            || KING_ATTACKS[index] & self.piece_bb(PieceType::King, by) != 0
+            || bishop_attacks(index, occupied) & (self.piece_bb(PieceType::Bishop, by) | queens)
+                != 0
+            || rook_attacks(index, occupied) & (self.piece_bb(PieceType::Rook, by) | queens) != 0
+    }
+
+    // One normal move from `from` to each square in `targets`
+    fn push_targets(&self, from: usize, targets: Bitboard, moves: &mut Vec<Move>) {
+        for to in bits(targets) {
+            self.push_move(moves, from, to, MoveFlag::Normal);
+        }
+    }
+
+    // Pawn moves onto the last rank are generated once per promotion piece
+    fn push_pawn_move(&self, moves: &mut Vec<Move>, from: usize, to: usize) {
+        self.push_move(moves, from, to, MoveFlag::Normal);
+        if to / 8 == 0 || to / 8 == 7 {
+            let mv = moves.pop().unwrap();
+            for promotion in PROMOTION_PIECES {
+                moves.push(Move {
+                    promotion: Some(promotion),
+                    ..mv
+                });
+            }
+        }
+    }
+
+    fn push_move(&self, moves: &mut Vec<Move>, from: usize, to: usize, flag: MoveFlag) {
+        moves.push(Move {
+            from: index_square(from),
+            to: index_square(to),
+            piece: self.mailbox[from].unwrap().p_type,
+            captured: self.mailbox[to].map(|captured_piece| captured_piece.p_type),
+            promotion: None,
+            flag,
+        });
+    }
+
+    /// The pieces of one type and color.
+    pub fn piece_bb(&self, p_type: PieceType, color: PColor) -> Bitboard {
+        self.pieces[p_type as usize] & self.colors[color as usize]
+    }
+
+    /// All pieces of one color.
+    pub fn color_bb(&self, color: PColor) -> Bitboard {
+        self.colors[color as usize]
+    }
+
+    pub fn occupied(&self) -> Bitboard {
+        self.colors[0] | self.colors[1]
+    }
+
+    pub fn piece_at(&self, square: (usize, usize)) -> Option<Piece> {
+        self.mailbox[square_index(square)]
+    }
+
+    /// The board as an 8x8 array indexed `[rank][file]`, built from the
+    /// bitboards.
+    pub fn squares(&self) -> [[Option<Piece>; 8]; 8] {
+        let mut squares = [[None; 8]; 8];
+        for (index, piece) in self.mailbox.iter().enumerate() {
+            squares[index / 8][index % 8] = *piece;
+        }
+        squares
+    }
+
+    // Every change to the pieces goes through these two, keeping the
+    // bitboards and the per-square lookup in step
+    fn put_piece(&mut self, piece: Piece, index: usize) {
+        self.pieces[piece.p_type as usize] |= 1 << index;
+        self.colors[piece.p_color as usize] |= 1 << index;
+        self.mailbox[index] = Some(piece);
+        self.hash ^= zobrist::piece_key(piece, index);
+    }
+
+    fn remove_piece(&mut self, index: usize) -> Option<Piece> {
+        let piece = self.mailbox[index].take()?;
+        self.pieces[piece.p_type as usize] &= !(1 << index);
+        self.colors[piece.p_color as usize] &= !(1 << index);
+        self.hash ^= zobrist::piece_key(piece, index);
+        Some(piece)
     }
 
     pub fn new_piece(&mut self, piece: Piece, rank: usize, file: usize) {
-        self.squares[rank][file] = Some(piece);
+        let index = square_index((rank, file));
+        self.remove_piece(index);
+        self.put_piece(piece, index);
+    }
+
+    /// Whether `mv` is one of the legal moves for the side to move.
+    pub fn is_legal(&mut self, mv: &Move) -> bool {
+        self.legal_moves().contains(mv)
+    }
+
+    /// Plays `mv` if it is legal, else leaves the board as it was. Unlike
+    /// `make_move` this is safe to call with moves from outside the engine.
+    pub fn apply_move(&mut self, mv: Move) -> Result<(), IllegalMove> {
+        if !self.is_legal(&mv) {
+            return Err(IllegalMove(mv));
+        }
+        self.make_move(mv);
+        Ok(())
+    }
+
+    /// Plays `mv` and records what is needed to take it back with
+    /// `unmake_move`. `mv` must be legal, see `apply_move`.
+    pub fn make_move(&mut self, mv: Move) {
+        self.history.push(HistoryEntry {
+            mv,
+            castling_rights: self.castling_rights,
+            en_passant: self.en_passant,
+            halfmove_clock: self.halfmove_clock,
+        });
+        self.hash ^= self.state_key();
+
+        let from = square_index(mv.from);
+        let to = square_index(mv.to);
+
+        // The captured pawn sits beside the moving pawn, not on the target square
+        if mv.flag == MoveFlag::EnPassant {
+            self.remove_piece(square_index((mv.from.0, mv.to.1)));
+        }
+        self.remove_piece(to);
+
+        let mut piece_to_move = self.remove_piece(from).unwrap();
+        // Replace a promoted pawn with the chosen piece
+        if let Some(promotion) = mv.promotion {
+            piece_to_move.p_type = promotion;
+        }
+        self.put_piece(piece_to_move, to);
+
+        // Move the rook along with the king
+        if mv.flag == MoveFlag::Castling {
+            let (rook_from, rook_to) = Board::castling_rook_squares(mv);
+            let rook = self.remove_piece(square_index(rook_from)).unwrap();
+            self.put_piece(rook, square_index(rook_to));
+        }
+        self.update_castling_rights(mv);
+
+        // A double push can be taken en passant on the next move only
+        self.en_passant = if mv.flag == MoveFlag::DoublePush {
+            Some(((mv.from.0 + mv.to.0) / 2, mv.from.1))
+        } else {
+            None
+        };
+
+        if mv.piece == PieceType::Pawn || mv.captured.is_some() {
+            self.halfmove_clock = 0;
+        } else {
+            self.halfmove_clock += 1;
+        }
+
+        if self.turn == PColor::Black {
+            self.fullmove_number += 1;
+        }
+
+        //Change the turn
+        self.turn = self.turn.opposite();
+
+        self.hash ^= self.state_key();
+        self.position_history.push(self.hash);
     }
 
-    pub fn make_move(&mut self, rank: usize, file: usize) {
-        if let Some((selected_rank, selected_file)) = self.selected_piece {
-            let piece_to_move = self.squares[selected_rank][selected_file].take();
-            
-            self.squares[rank as usize][file as usize] = piece_to_move;
+    /// Takes back the last move, restoring the exact previous state.
+    /// Returns the move taken back, or `None` at the start of the game.
+    pub fn unmake_move(&mut self) -> Option<Move> {
+        let entry = self.history.pop()?;
+        let mv = entry.mv;
+        self.position_history.pop();
+        self.hash ^= self.state_key();
+
+        //Change the turn back
+        self.turn = self.turn.opposite();
+        if self.turn == PColor::Black {
+            self.fullmove_number -= 1;
+        }
+
+        // Put the piece back, turning a promoted piece into a pawn again
+        self.remove_piece(square_index(mv.to));
+        self.put_piece(
+            Piece {
+                p_type: mv.piece,
+                p_color: self.turn,
+            },
+            square_index(mv.from),
+        );
+
+        if let Some(captured) = mv.captured {
+            let captured_square = if mv.flag == MoveFlag::EnPassant {
+                (mv.from.0, mv.to.1)
+            } else {
+                mv.to
+            };
+            self.put_piece(
+                Piece {
+                    p_type: captured,
+                    p_color: self.turn.opposite(),
+                },
+                square_index(captured_square),
+            );
+        }
+
+        if mv.flag == MoveFlag::Castling {
+            let (rook_from, rook_to) = Board::castling_rook_squares(mv);
+            let rook = self.remove_piece(square_index(rook_to)).unwrap();
+            self.put_piece(rook, square_index(rook_from));
+        }
+
+        self.castling_rights = entry.castling_rights;
+        self.en_passant = entry.en_passant;
+        self.halfmove_clock = entry.halfmove_clock;
+        self.hash ^= self.state_key();
 
-            self.selected_piece = Some((rank as usize, file as usize));
+        Some(mv)
+    }
+
+    /// Every move made on this board so far, oldest first.
+    pub fn history(&self) -> &[HistoryEntry] {
+        &self.history
+    }
 
-            // Deselect the piece
-            self.selected_piece = None;
+    /// Zobrist hash of the position: pieces, side to move, castling
+    /// rights and en passant file. Equal positions hash the same however
+    /// they were reached.
+    pub fn hash(&self) -> u64 {
+        self.hash
+    }
 
-            //Change the turn
-            self.turn = match self.turn {
-                PColor::White => PColor::Black,
-                PColor::Black => PColor::White,
+    pub fn turn(&self) -> PColor {
+        self.turn
+    }
+
+    pub fn castling_rights(&self) -> CastlingRights {
+        self.castling_rights
+    }
+
+    /// Square a pawn skipped with a double push on the previous move.
+    pub fn en_passant(&self) -> Option<(usize, usize)> {
+        self.en_passant
+    }
+
+    fn compute_hash(&self) -> u64 {
+        let mut hash = self.state_key();
+        for index in bits(self.occupied()) {
+            hash ^= zobrist::piece_key(self.mailbox[index].unwrap(), index);
+        }
+        hash
+    }
+
+    // The part of the hash that is not about pieces. Make and unmake xor it
+    // out before changing anything and back in afterwards
+    fn state_key(&self) -> u64 {
+        let mut key = zobrist::castling_key(self.castling_rights);
+        if self.turn == PColor::Black {
+            key ^= zobrist::side_key();
+        }
+        // The en passant square only matters when a pawn can actually use it
+        if let Some(square) = self.en_passant {
+            let capturers = PAWN_ATTACKS[self.turn.opposite() as usize][square_index(square)];
+            if capturers & self.piece_bb(PieceType::Pawn, self.turn) != 0 {
+                key ^= zobrist::en_passant_key(square.1);
+            }
+        }
+        key
+    }
+
+    /// Whether the game is over, and why.
+    pub fn game_status(&mut self) -> GameStatus {
+        if self.legal_moves().is_empty() {
+            return if self.is_in_check(self.turn) {
+                GameStatus::Checkmate(self.turn.opposite())
+            } else {
+                GameStatus::Stalemate
             };
         }
+        if self.insufficient_material() {
+            return GameStatus::Draw(DrawReason::InsufficientMaterial);
+        }
+        if self.halfmove_clock >= 100 {
+            return GameStatus::Draw(DrawReason::FiftyMoveRule);
+        }
+        if self.repetitions() >= 3 {
+            return GameStatus::Draw(DrawReason::ThreefoldRepetition);
+        }
+        GameStatus::Ongoing
     }
 
-    fn copy(&mut self) -> Board {
-        let mut board_copy = Board::new();
-        for rank in 0..8 {
-            for file in 0..8 {
-                if let Some(piece) = self.squares[rank][file] {
-                    board_copy.new_piece(piece, rank, file);
+    /// How often the current position has occurred, counting itself.
+    pub fn repetitions(&self) -> usize {
+        // Positions before the last capture or pawn move cannot repeat
+        self.position_history
+            .iter()
+            .rev()
+            .take(self.halfmove_clock as usize + 1)
+            .filter(|&&hash| hash == self.hash)
+            .count()
+    }
+
+    /// Neither side can possibly mate: bare kings, a single minor piece,
+    /// or only bishops that all stand on squares of one color.
+    pub fn insufficient_material(&self) -> bool {
+        let heavy = self.pieces[PieceType::Pawn as usize]
+            | self.pieces[PieceType::Rook as usize]
+            | self.pieces[PieceType::Queen as usize];
+        if heavy != 0 {
+            return false;
+        }
+        let knights = self.pieces[PieceType::Knight as usize].count_ones();
+        let bishops = self.pieces[PieceType::Bishop as usize];
+        let bishops_on_one_color = bishops & DARK_SQUARES == 0 || bishops & !DARK_SQUARES == 0;
+        match knights {
+            0 => bishops_on_one_color,
+            1 => bishops == 0,
+            _ => false,
+        }
+    }
+
+    /// Whether `color` could mate by any series of legal moves, with the
+    /// opponent's help. A lone king never can. A single knight, or bishops
+    /// that all stand on one colour, need an opposing piece to hem the
+    /// king in: anything but a queen for the knight, a pawn, knight or
+    /// bishop of the other colour for the bishops.
+    pub fn has_mating_material(&self, color: PColor) -> bool {
+        let own = self.color_bb(color);
+        let theirs = self.color_bb(color.opposite());
+        let heavy = self.pieces[PieceType::Pawn as usize]
+            | self.pieces[PieceType::Rook as usize]
+            | self.pieces[PieceType::Queen as usize];
+        if heavy & own != 0 {
+            return true;
+        }
+        let knights = self.pieces[PieceType::Knight as usize] & own;
+        let bishops = self.pieces[PieceType::Bishop as usize] & own;
+        let blockers = theirs & !self.pieces[PieceType::King as usize];
+        match (knights.count_ones(), bishops) {
+            (0, 0) => false,
+            (1, 0) => blockers & !self.pieces[PieceType::Queen as usize] != 0,
+            (0, _) if bishops & DARK_SQUARES == 0 || bishops & !DARK_SQUARES == 0 => {
+                let other_colour = if bishops & DARK_SQUARES == 0 {
+                    DARK_SQUARES
                 } else {
-                    board_copy.squares[rank][file] = None;
-                }
+                    !DARK_SQUARES
+                };
+                let minor_blockers = self.pieces[PieceType::Pawn as usize]
+                    | self.pieces[PieceType::Knight as usize]
+                    | self.pieces[PieceType::Bishop as usize] & other_colour;
+                blockers & minor_blockers != 0
             }
+            _ => true,
         }
+    }
 
-        board_copy.selected_piece = self.selected_piece;
-        board_copy.turn = self.turn;
+    /// The rook's `(from, to)` squares for a castling move.
+    pub fn castling_rook_squares(mv: Move) -> ((usize, usize), (usize, usize)) {
+        let rank = mv.from.0;
+        if mv.to.1 == 6 {
+            ((rank, 7), (rank, 5))
+        } else {
+            ((rank, 0), (rank, 3))
+        }
+    }
 
-        board_copy
+    /// Drops the castling rights lost by playing `mv`: moving the king or
+    /// a rook, or capturing a rook on its starting square.
+    fn update_castling_rights(&mut self, mv: Move) {
+        self.castling_rights.remove_for_square(mv.from);
+        self.castling_rights.remove_for_square(mv.to);
     }
 
-    pub fn legal_moves(&mut self) -> Vec<(usize, usize)> {
+    /// Every legal move for the side to move.
+    pub fn legal_moves(&mut self) -> Vec<Move> {
         //generate moves
-        let moves = self.move_generation();
+        let moves = self.generate_moves(self.turn);
         let mut legalmoves = Vec::new();
-        //perform the move on a copy of the board
-        for (rank, file) in moves {
-            let mut board_copy = self.copy();
-            board_copy.make_move(rank, file);
-
-            if !board_copy.in_check() {
-                legalmoves.push((rank, file));
+        let color = self.turn;
+        //try each move and take it back again
+        for mv in moves {
+            self.make_move(mv);
+            if !self.is_in_check(color) {
+                legalmoves.push(mv);
             }
+            self.unmake_move();
         }
         legalmoves
     }
 
-    pub fn find(&mut self, piece_to_find: Piece) -> Option<(usize, usize)> {
-        for rank in 0..8 {
-            for file in 0..8 {
-                if let Some(piece) = self.squares[rank][file] {
-                    if piece == piece_to_find {
-                        return Some((rank, file));
-                    }
-                }
-            }
-        }
-        None
+    pub fn find(&self, piece_to_find: Piece) -> Option<(usize, usize)> {
+        bits(self.piece_bb(piece_to_find.p_type, piece_to_find.p_color))
+            .next()
+            .map(index_square)
     }
 
-    pub fn in_check(&mut self) -> bool {
-        let color = match self.turn {
-            PColor::White => PColor::Black,
-            PColor::Black => PColor::White,
-        };
-
-        //check if the king is under attack by any piece
-        if let Some(king_position) = self.find(Piece {
-            p_type: (PieceType::King),
-            p_color: (color),
+    /// Whether the king of `color` is attacked, whoever is to move.
+    pub fn is_in_check(&self, color: PColor) -> bool {
+        match self.find(Piece {
+            p_type: PieceType::King,
+            p_color: color,
         }) {
-            for rank in 0..8 {
-                for file in 0..8 {
-                    if let Some(piece) = self.squares[rank][file] {
-                        if piece.p_color != color {
-                            self.selected_piece = Some((rank, file));
-                            let moves = self.move_generation();
-                            if moves.contains(&king_position) {
-                                return true;
-                            }
-                        }
-                    }
-                }
-            }
+            Some(king_position) => self.is_square_attacked(king_position, color.opposite()),
+            None => false,
         }
-
-        false
     }
 }
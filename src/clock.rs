@@ -0,0 +1,173 @@
+use std::fmt;
+use std::time::{Duration, Instant};
+
+use crate::chess::PColor;
+
+/// How much time each side gets.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum TimeControl {
+    /// The whole game in `base`.
+    SuddenDeath { base: Duration },
+    /// `increment` is added after every move.
+    Fischer { base: Duration, increment: Duration },
+    /// Up to `delay` of the time spent on a move is given back.
+    Bronstein { base: Duration, delay: Duration },
+}
+
+impl TimeControl {
+    /// Reads "5" (five minutes), "3+2" (three minutes and a two second
+    /// increment) or "5d3" (five minutes and a three second delay).
+    pub fn parse(text: &str) -> Result<TimeControl, String> {
+        let minutes = |value: &str| {
+            value
+                .parse::<f64>()
+                .ok()
+                .filter(|m| m.is_finite() && *m > 0.0)
+        };
+        let seconds = |value: &str| value.parse::<u64>().ok().map(Duration::from_secs);
+        let invalid = || format!("'{}' is not a time control like 5, 3+2 or 5d3", text);
+
+        let (base, extra) = match text.split_once(['+', 'd']) {
+            Some((base, extra)) => (base, Some(extra)),
+            None => (text, None),
+        };
+        let base = Duration::try_from_secs_f64(minutes(base).ok_or_else(invalid)? * 60.0)
+            .map_err(|_| invalid())?;
+        Ok(match extra {
+            None => TimeControl::SuddenDeath { base },
+            Some(extra) => {
+                let extra = seconds(extra).ok_or_else(invalid)?;
+                if text.contains('+') {
+                    TimeControl::Fischer {
+                        base,
+                        increment: extra,
+                    }
+                } else {
+                    TimeControl::Bronstein { base, delay: extra }
+                }
+            }
+        })
+    }
+
+    pub fn base(&self) -> Duration {
+        match *self {
+            TimeControl::SuddenDeath { base }
+            | TimeControl::Fischer { base, .. }
+            | TimeControl::Bronstein { base, .. } => base,
+        }
+    }
+
+    /// Time added after every move, for the engine to plan with.
+    pub fn increment(&self) -> Duration {
+        match *self {
+            TimeControl::SuddenDeath { .. } => Duration::ZERO,
+            TimeControl::Fischer { increment, .. } => increment,
+            TimeControl::Bronstein { delay, .. } => delay,
+        }
+    }
+}
+
+impl fmt::Display for TimeControl {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let minutes = self.base().as_secs_f64() / 60.0;
+        match self {
+            TimeControl::SuddenDeath { .. } => write!(f, "{}", minutes),
+            TimeControl::Fischer { increment, .. } => {
+                write!(f, "{}+{}", minutes, increment.as_secs())
+            }
+            TimeControl::Bronstein { delay, .. } => write!(f, "{}d{}", minutes, delay.as_secs()),
+        }
+    }
+}
+
+/// A chess clock: the time left for both sides, with at most one of them
+/// running. Every method takes the current time so the clock can be
+/// driven by anything, not only the wall clock.
+#[derive(Clone, Debug)]
+pub struct Clock {
+    control: TimeControl,
+    remaining: [Duration; 2],
+    // The side whose time is running and when it started
+    running: Option<(PColor, Instant)>,
+}
+
+impl Clock {
+    pub fn new(control: TimeControl) -> Clock {
+        Clock {
+            control,
+            remaining: [control.base(); 2],
+            running: None,
+        }
+    }
+
+    pub fn control(&self) -> TimeControl {
+        self.control
+    }
+
+    pub fn running(&self) -> Option<PColor> {
+        self.running.map(|(color, _)| color)
+    }
+
+    /// Time left for `color` at `now`, never below zero.
+    pub fn remaining(&self, color: PColor, now: Instant) -> Duration {
+        let left = self.remaining[color as usize];
+        match self.running {
+            Some((running, since)) if running == color => {
+                left.saturating_sub(now.saturating_duration_since(since))
+            }
+            _ => left,
+        }
+    }
+
+    /// The side whose time has run out, if any.
+    pub fn flagged(&self, now: Instant) -> Option<PColor> {
+        [PColor::White, PColor::Black]
+            .into_iter()
+            .find(|&color| self.remaining(color, now).is_zero())
+    }
+
+    /// Starts the time of `color`, stopping the other side's without
+    /// any increment.
+    pub fn start(&mut self, color: PColor, now: Instant) {
+        self.stop(now);
+        self.running = Some((color, now));
+    }
+
+    /// Stops the clock, keeping the time used so far.
+    pub fn stop(&mut self, now: Instant) -> Option<Duration> {
+        let (color, since) = self.running.take()?;
+        let spent = now.saturating_duration_since(since);
+        let left = &mut self.remaining[color as usize];
+        *left = left.saturating_sub(spent);
+        Some(spent)
+    }
+
+    /// The running side finished its move: its time is charged, any
+    /// increment or delay credited, and the opponent's time starts.
+    pub fn press(&mut self, now: Instant) {
+        let Some(color) = self.running() else {
+            return;
+        };
+        let spent = self.stop(now).unwrap_or_default();
+        let left = &mut self.remaining[color as usize];
+        // A flag that has fallen stays down
+        if !left.is_zero() {
+            *left = left.saturating_add(match self.control {
+                TimeControl::SuddenDeath { .. } => Duration::ZERO,
+                TimeControl::Fischer { increment, .. } => increment,
+                TimeControl::Bronstein { delay, .. } => spent.min(delay),
+            });
+        }
+        self.running = Some((color.opposite(), now));
+    }
+}
+
+/// `duration` as shown on a clock: "m:ss", with tenths below ten seconds.
+pub fn format_clock(duration: Duration) -> String {
+    let seconds = duration.as_secs();
+    if seconds < 10 {
+        format!("0:{:02}.{}", seconds, duration.subsec_millis() / 100)
+    } else {
+        format!("{}:{:02}", seconds / 60, seconds % 60)
+    }
+}
@@ -0,0 +1,464 @@
+use std::fmt;
+use std::ops::{AddAssign, Neg, Sub};
+
+use crate::bitboard::{
+    bishop_attacks, bits, index_square, queen_attacks, rook_attacks, Bitboard, KING_ATTACKS,
+    KNIGHT_ATTACKS,
+};
+use crate::chess::{Board, PColor, PieceType};
+
+/// Phase with every piece on the board; the phase drops to 0 as knights,
+/// bishops, rooks and queens come off.
+pub const MAX_PHASE: i32 = 24;
+
+/// A middlegame and an endgame score, blended by game phase.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct Score {
+    pub mg: i32,
+    pub eg: i32,
+}
+
+impl Score {
+    pub const fn new(mg: i32, eg: i32) -> Score {
+        Score { mg, eg }
+    }
+
+    fn times(self, factor: i32) -> Score {
+        Score::new(self.mg * factor, self.eg * factor)
+    }
+}
+
+impl AddAssign for Score {
+    fn add_assign(&mut self, other: Score) {
+        self.mg += other.mg;
+        self.eg += other.eg;
+    }
+}
+
+impl Sub for Score {
+    type Output = Score;
+
+    fn sub(self, other: Score) -> Score {
+        Score::new(self.mg - other.mg, self.eg - other.eg)
+    }
+}
+
+impl Neg for Score {
+    type Output = Score;
+
+    fn neg(self) -> Score {
+        Score::new(-self.mg, -self.eg)
+    }
+}
+
+/// Every evaluation term on its own, each as White's score minus Black's,
+/// so it is easy to see why a position is liked.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct Evaluation {
+    pub material: Score,
+    pub piece_squares: Score,
+    pub pawn_structure: Score,
+    pub king_safety: Score,
+    pub mobility: Score,
+    /// From `MAX_PHASE` in the opening down to 0 in a pawn ending.
+    pub phase: i32,
+}
+
+impl Evaluation {
+    /// Blends the middlegame and endgame parts of a score by phase.
+    pub fn taper(&self, score: Score) -> i32 {
+        (score.mg * self.phase + score.eg * (MAX_PHASE - self.phase)) / MAX_PHASE
+    }
+
+    /// Sum of all terms in centipawns, from White's point of view.
+    pub fn total(&self) -> i32 {
+        self.taper(self.material)
+            + self.taper(self.piece_squares)
+            + self.taper(self.pawn_structure)
+            + self.taper(self.king_safety)
+            + self.taper(self.mobility)
+    }
+}
+
+impl fmt::Display for Evaluation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "Term                MG     EG  Final")?;
+        for (name, score) in [
+            ("Material", self.material),
+            ("Piece squares", self.piece_squares),
+            ("Pawn structure", self.pawn_structure),
+            ("King safety", self.king_safety),
+            ("Mobility", self.mobility),
+        ] {
+            writeln!(
+                f,
+                "{:<16}{:>6} {:>6} {:>6}",
+                name,
+                score.mg,
+                score.eg,
+                self.taper(score)
+            )?;
+        }
+        writeln!(f, "Phase {}/{}", self.phase, MAX_PHASE)?;
+        write!(f, "Total (White's view): {}", self.total())
+    }
+}
+
+/// Material value of a piece in centipawns, middlegame and endgame.
+pub fn material_value(p_type: PieceType) -> Score {
+    match p_type {
+        PieceType::Pawn => Score::new(100, 120),
+        PieceType::Knight => Score::new(320, 300),
+        PieceType::Bishop => Score::new(330, 320),
+        PieceType::Rook => Score::new(500, 530),
+        PieceType::Queen => Score::new(900, 950),
+        PieceType::King => Score::new(0, 0),
+    }
+}
+
+/// Middlegame material value, for move ordering and exchanges.
+pub fn piece_value(p_type: PieceType) -> i32 {
+    material_value(p_type).mg
+}
+
+fn phase_weight(p_type: PieceType) -> i32 {
+    match p_type {
+        PieceType::Knight | PieceType::Bishop => 1,
+        PieceType::Rook => 2,
+        PieceType::Queen => 4,
+        PieceType::Pawn | PieceType::King => 0,
+    }
+}
+
+// Piece-square tables from White's side, written as seen on a diagram:
+// the first row is rank 8, the last row rank 1
+#[rustfmt::skip]
+const PAWN_MG: [i32; 64] = [
+     0,   0,   0,   0,   0,   0,   0,   0,
+    50,  50,  50,  50,  50,  50,  50,  50,
+    10,  10,  20,  30,  30,  20,  10,  10,
+     5,   5,  10,  25,  25,  10,   5,   5,
+     0,   0,   0,  20,  20,   0,   0,   0,
+     5,  -5, -10,   0,   0, -10,  -5,   5,
+     5,  10,  10, -20, -20,  10,  10,   5,
+     0,   0,   0,   0,   0,   0,   0,   0,
+];
+
+#[rustfmt::skip]
+const PAWN_EG: [i32; 64] = [
+     0,   0,   0,   0,   0,   0,   0,   0,
+    80,  80,  80,  80,  80,  80,  80,  80,
+    50,  50,  50,  50,  50,  50,  50,  50,
+    30,  30,  30,  30,  30,  30,  30,  30,
+    20,  20,  20,  20,  20,  20,  20,  20,
+    10,  10,  10,  10,  10,  10,  10,  10,
+    10,  10,  10,  10,  10,  10,  10,  10,
+     0,   0,   0,   0,   0,   0,   0,   0,
+];
+
+#[rustfmt::skip]
+const KNIGHT_TABLE: [i32; 64] = [
+   -50, -40, -30, -30, -30, -30, -40, -50,
+   -40, -20,   0,   0,   0,   0, -20, -40,
+   -30,   0,  10,  15,  15,  10,   0, -30,
+   -30,   5,  15,  20,  20,  15,   5, -30,
+   -30,   0,  15,  20,  20,  15,   0, -30,
+   -30,   5,  10,  15,  15,  10,   5, -30,
+   -40, -20,   0,   5,   5,   0, -20, -40,
+   -50, -40, -30, -30, -30, -30, -40, -50,
+];
+
+#[rustfmt::skip]
+const BISHOP_TABLE: [i32; 64] = [
+   -20, -10, -10, -10, -10, -10, -10, -20,
+   -10,   0,   0,   0,   0,   0,   0, -10,
+   -10,   0,   5,  10,  10,   5,   0, -10,
+   -10,   5,   5,  10,  10,   5,   5, -10,
+   -10,   0,  10,  10,  10,  10,   0, -10,
+   -10,  10,  10,  10,  10,  10,  10, -10,
+   -10,   5,   0,   0,   0,   0,   5, -10,
+   -20, -10, -10, -10, -10, -10, -10, -20,
+];
+
+#[rustfmt::skip]
+const ROOK_TABLE: [i32; 64] = [
+     0,   0,   0,   0,   0,   0,   0,   0,
+     5,  10,  10,  10,  10,  10,  10,   5,
+    -5,   0,   0,   0,   0,   0,   0,  -5,
+    -5,   0,   0,   0,   0,   0,   0,  -5,
+    -5,   0,   0,   0,   0,   0,   0,  -5,
+    -5,   0,   0,   0,   0,   0,   0,  -5,
+    -5,   0,   0,   0,   0,   0,   0,  -5,
+     0,   0,   0,   5,   5,   0,   0,   0,
+];
+
+#[rustfmt::skip]
+const QUEEN_TABLE: [i32; 64] = [
+   -20, -10, -10,  -5,  -5, -10, -10, -20,
+   -10,   0,   0,   0,   0,   0,   0, -10,
+   -10,   0,   5,   5,   5,   5,   0, -10,
+    -5,   0,   5,   5,   5,   5,   0,  -5,
+     0,   0,   5,   5,   5,   5,   0,  -5,
+   -10,   5,   5,   5,   5,   5,   0, -10,
+   -10,   0,   5,   0,   0,   0,   0, -10,
+   -20, -10, -10,  -5,  -5, -10, -10, -20,
+];
+
+#[rustfmt::skip]
+const KING_MG: [i32; 64] = [
+   -30, -40, -40, -50, -50, -40, -40, -30,
+   -30, -40, -40, -50, -50, -40, -40, -30,
+   -30, -40, -40, -50, -50, -40, -40, -30,
+   -30, -40, -40, -50, -50, -40, -40, -30,
+   -20, -30, -30, -40, -40, -30, -30, -20,
+   -10, -20, -20, -20, -20, -20, -20, -10,
+    20,  20,   0,   0,   0,   0,  20,  20,
+    20,  30,  10,   0,   0,  10,  30,  20,
+];
+
+#[rustfmt::skip]
+const KING_EG: [i32; 64] = [
+   -50, -40, -30, -20, -20, -30, -40, -50,
+   -30, -20, -10,   0,   0, -10, -20, -30,
+   -30, -10,  20,  30,  30,  20, -10, -30,
+   -30, -10,  30,  40,  40,  30, -10, -30,
+   -30, -10,  30,  40,  40,  30, -10, -30,
+   -30, -10,  20,  30,  30,  20, -10, -30,
+   -30, -30,   0,   0,   0,   0, -30, -30,
+   -50, -30, -30, -30, -30, -30, -30, -50,
+];
+
+/// Piece-square bonus for a piece of `color` on square `index`.
+pub fn piece_square(p_type: PieceType, color: PColor, index: usize) -> Score {
+    let (rank, file) = index_square(index);
+    // The tables are drawn from White's side, so Black reads them upside down
+    let row = match color {
+        PColor::White => 7 - rank,
+        PColor::Black => rank,
+    };
+    let i = row * 8 + file;
+    match p_type {
+        PieceType::Pawn => Score::new(PAWN_MG[i], PAWN_EG[i]),
+        PieceType::Knight => Score::new(KNIGHT_TABLE[i], KNIGHT_TABLE[i]),
+        PieceType::Bishop => Score::new(BISHOP_TABLE[i], BISHOP_TABLE[i]),
+        PieceType::Rook => Score::new(ROOK_TABLE[i], ROOK_TABLE[i]),
+        PieceType::Queen => Score::new(QUEEN_TABLE[i], QUEEN_TABLE[i]),
+        PieceType::King => Score::new(KING_MG[i], KING_EG[i]),
+    }
+}
+
+const PIECE_TYPES: [PieceType; 6] = [
+    PieceType::Pawn,
+    PieceType::Knight,
+    PieceType::Bishop,
+    PieceType::Rook,
+    PieceType::Queen,
+    PieceType::King,
+];
+
+const FILE_A: Bitboard = 0x0101_0101_0101_0101;
+
+const DOUBLED_PAWN: Score = Score::new(-10, -20);
+const ISOLATED_PAWN: Score = Score::new(-10, -15);
+// Indexed by how far the pawn has advanced, from its own side
+const PASSED_PAWN: [Score; 8] = [
+    Score::new(0, 0),
+    Score::new(5, 10),
+    Score::new(10, 20),
+    Score::new(15, 35),
+    Score::new(25, 60),
+    Score::new(40, 90),
+    Score::new(60, 130),
+    Score::new(0, 0),
+];
+
+const PAWN_SHIELD: i32 = 10;
+const OPEN_FILE_NEAR_KING: i32 = -15;
+const KING_ZONE_ATTACK: i32 = -6;
+
+fn file_mask(file: usize) -> Bitboard {
+    FILE_A << file
+}
+
+fn adjacent_files(file: usize) -> Bitboard {
+    let mut mask = 0;
+    if file > 0 {
+        mask |= file_mask(file - 1);
+    }
+    if file < 7 {
+        mask |= file_mask(file + 1);
+    }
+    mask
+}
+
+// All squares on ranks strictly ahead of `rank`, seen from `color`
+fn ranks_ahead(rank: usize, color: PColor) -> Bitboard {
+    match color {
+        PColor::White if rank < 7 => !0 << ((rank + 1) * 8),
+        PColor::Black if rank > 0 => !0 >> ((8 - rank) * 8),
+        _ => 0,
+    }
+}
+
+fn relative_rank(rank: usize, color: PColor) -> usize {
+    match color {
+        PColor::White => rank,
+        PColor::Black => 7 - rank,
+    }
+}
+
+fn material(board: &Board, color: PColor) -> Score {
+    let mut score = Score::default();
+    for p_type in PIECE_TYPES {
+        let count = board.piece_bb(p_type, color).count_ones() as i32;
+        score += material_value(p_type).times(count);
+    }
+    score
+}
+
+fn piece_squares(board: &Board, color: PColor) -> Score {
+    let mut score = Score::default();
+    for p_type in PIECE_TYPES {
+        for index in bits(board.piece_bb(p_type, color)) {
+            score += piece_square(p_type, color, index);
+        }
+    }
+    score
+}
+
+/// Doubled, isolated and passed pawns of one side.
+pub fn pawn_structure(board: &Board, color: PColor) -> Score {
+    let pawns = board.piece_bb(PieceType::Pawn, color);
+    let enemy_pawns = board.piece_bb(PieceType::Pawn, color.opposite());
+    let mut score = Score::default();
+
+    for file in 0..8 {
+        let on_file = (pawns & file_mask(file)).count_ones() as i32;
+        if on_file > 1 {
+            score += DOUBLED_PAWN.times(on_file - 1);
+        }
+        if on_file > 0 && pawns & adjacent_files(file) == 0 {
+            score += ISOLATED_PAWN.times(on_file);
+        }
+    }
+
+    for index in bits(pawns) {
+        let (rank, file) = index_square(index);
+        // No enemy pawn ahead on this or a neighbouring file can stop it
+        let front_span = (file_mask(file) | adjacent_files(file)) & ranks_ahead(rank, color);
+        if enemy_pawns & front_span == 0 {
+            score += PASSED_PAWN[relative_rank(rank, color)];
+        }
+    }
+    score
+}
+
+/// Pawn shield and open files in front of the king, and enemy pieces
+/// bearing on the squares around it. Only matters in the middlegame.
+pub fn king_safety(board: &Board, color: PColor) -> Score {
+    let king = match bits(board.piece_bb(PieceType::King, color)).next() {
+        Some(king) => king,
+        None => return Score::default(),
+    };
+    let (rank, file) = index_square(king);
+    let pawns = board.piece_bb(PieceType::Pawn, color);
+    let mut safety = 0;
+
+    // Own pawns one or two ranks in front of the king
+    let shield_files = file_mask(file) | adjacent_files(file);
+    let shield_ranks = ranks_ahead(rank, color)
+        & !ranks_ahead(
+            match color {
+                PColor::White => (rank + 2).min(7),
+                PColor::Black => rank.saturating_sub(2),
+            },
+            color,
+        );
+    let shield = (pawns & shield_files & shield_ranks).count_ones() as i32;
+    safety += PAWN_SHIELD * shield.min(3);
+
+    for f in file.saturating_sub(1)..=(file + 1).min(7) {
+        if pawns & file_mask(f) == 0 {
+            safety += OPEN_FILE_NEAR_KING;
+        }
+    }
+
+    // Weighted attacks of enemy pieces on the king and its neighbours
+    let zone = KING_ATTACKS[king] | (1 << king);
+    let occupied = board.occupied();
+    let enemy = color.opposite();
+    let mut attack_units = 0;
+    for index in bits(board.piece_bb(PieceType::Knight, enemy)) {
+        attack_units += 2 * (KNIGHT_ATTACKS[index] & zone).count_ones() as i32;
+    }
+    for index in bits(board.piece_bb(PieceType::Bishop, enemy)) {
+        attack_units += 2 * (bishop_attacks(index, occupied) & zone).count_ones() as i32;
+    }
+    for index in bits(board.piece_bb(PieceType::Rook, enemy)) {
+        attack_units += 3 * (rook_attacks(index, occupied) & zone).count_ones() as i32;
+    }
+    for index in bits(board.piece_bb(PieceType::Queen, enemy)) {
+        attack_units += 5 * (queen_attacks(index, occupied) & zone).count_ones() as i32;
+    }
+    safety += KING_ZONE_ATTACK * attack_units;
+
+    Score::new(safety, 0)
+}
+
+/// Squares each piece can move to, compared to a typical count.
+pub fn mobility(board: &Board, color: PColor) -> Score {
+    let own = board.color_bb(color);
+    let occupied = board.occupied();
+    let mut score = Score::default();
+    let mut add = |targets: Bitboard, typical: i32, weight: Score| {
+        score += weight.times((targets & !own).count_ones() as i32 - typical);
+    };
+    for index in bits(board.piece_bb(PieceType::Knight, color)) {
+        add(KNIGHT_ATTACKS[index], 4, Score::new(4, 4));
+    }
+    for index in bits(board.piece_bb(PieceType::Bishop, color)) {
+        add(bishop_attacks(index, occupied), 7, Score::new(5, 5));
+    }
+    for index in bits(board.piece_bb(PieceType::Rook, color)) {
+        add(rook_attacks(index, occupied), 7, Score::new(2, 4));
+    }
+    for index in bits(board.piece_bb(PieceType::Queen, color)) {
+        add(queen_attacks(index, occupied), 14, Score::new(1, 2));
+    }
+    score
+}
+
+/// Game phase from the pieces left on the board.
+pub fn phase(board: &Board) -> i32 {
+    let mut phase = 0;
+    for p_type in PIECE_TYPES {
+        let count = (board.piece_bb(p_type, PColor::White) | board.piece_bb(p_type, PColor::Black))
+            .count_ones() as i32;
+        phase += phase_weight(p_type) * count;
+    }
+    phase.min(MAX_PHASE)
+}
+
+/// All evaluation terms of a position, from White's point of view.
+pub fn evaluate_terms(board: &Board) -> Evaluation {
+    let white = PColor::White;
+    let black = PColor::Black;
+    Evaluation {
+        material: material(board, white) - material(board, black),
+        piece_squares: piece_squares(board, white) - piece_squares(board, black),
+        pawn_structure: pawn_structure(board, white) - pawn_structure(board, black),
+        king_safety: king_safety(board, white) - king_safety(board, black),
+        mobility: mobility(board, white) - mobility(board, black),
+        phase: phase(board),
+    }
+}
+
+/// Static evaluation in centipawns from the point of view of the side to
+/// move, as the search wants it.
+pub fn evaluate(board: &Board) -> i32 {
+    let total = evaluate_terms(board).total();
+    match board.turn {
+        PColor::White => total,
+        PColor::Black => -total,
+    }
+}
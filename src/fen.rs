@@ -0,0 +1,312 @@
+use std::fmt;
+
+use crate::chess::{parse_square, square_name, Board, CastlingRights, PColor, Piece, PieceType};
+
+/// The standard starting position.
+pub const START_FEN: &str = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+
+/// Why a FEN string could not be read.
+#[derive(Clone, Debug, PartialEq)]
+pub enum FenError {
+    /// A FEN needs 6 fields (the two move clocks may be left out).
+    WrongFieldCount(usize),
+    /// The placement field does not describe exactly 8 ranks.
+    WrongRankCount(usize),
+    /// A rank of the placement field does not add up to 8 squares.
+    WrongRankLength {
+        rank: usize,
+        length: usize,
+    },
+    InvalidPiece(char),
+    /// Each side needs exactly one king.
+    KingCount(PColor, usize),
+    /// Pawns can never stand on the first or last rank.
+    PawnOnBackRank((usize, usize)),
+    InvalidSideToMove(String),
+    InvalidCastling(String),
+    InvalidEnPassant(String),
+    /// The en passant square is not behind a pawn the opponent just
+    /// pushed two squares.
+    ImpossibleEnPassant(String),
+    /// The side that just moved is still in check.
+    OpponentInCheck(PColor),
+    InvalidHalfmoveClock(String),
+    InvalidFullmoveNumber(String),
+}
+
+impl fmt::Display for FenError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            FenError::WrongFieldCount(count) => {
+                write!(f, "expected 6 space separated fields, found {}", count)
+            }
+            FenError::WrongRankCount(count) => {
+                write!(
+                    f,
+                    "expected 8 ranks in the piece placement, found {}",
+                    count
+                )
+            }
+            FenError::WrongRankLength { rank, length } => write!(
+                f,
+                "rank {} describes {} squares instead of 8",
+                rank + 1,
+                length
+            ),
+            FenError::InvalidPiece(c) => write!(f, "'{}' is not a piece letter", c),
+            FenError::KingCount(color, count) => {
+                write!(f, "{:?} has {} kings, expected exactly 1", color, count)
+            }
+            FenError::InvalidSideToMove(field) => {
+                write!(f, "side to move must be 'w' or 'b', found '{}'", field)
+            }
+            FenError::InvalidCastling(field) => {
+                write!(
+                    f,
+                    "castling rights must be '-' or from 'KQkq', found '{}'",
+                    field
+                )
+            }
+            FenError::InvalidEnPassant(field) => write!(
+                f,
+                "en passant square must be '-' or on rank 3 or 6, found '{}'",
+                field
+            ),
+            FenError::PawnOnBackRank(square) => {
+                write!(
+                    f,
+                    "pawn on {}, pawns cannot stand on rank 1 or 8",
+                    square_name(*square)
+                )
+            }
+            FenError::ImpossibleEnPassant(field) => write!(
+                f,
+                "en passant square '{}' is not behind a pawn that just moved two squares",
+                field
+            ),
+            FenError::OpponentInCheck(color) => {
+                write!(f, "{:?} is in check but it is not their move", color)
+            }
+            FenError::InvalidHalfmoveClock(field) => {
+                write!(f, "halfmove clock must be a number, found '{}'", field)
+            }
+            FenError::InvalidFullmoveNumber(field) => {
+                write!(
+                    f,
+                    "fullmove number must be a positive number, found '{}'",
+                    field
+                )
+            }
+        }
+    }
+}
+
+impl std::error::Error for FenError {}
+
+fn piece_from_char(c: char) -> Option<Piece> {
+    let p_type = match c.to_ascii_lowercase() {
+        'p' => PieceType::Pawn,
+        'r' => PieceType::Rook,
+        'n' => PieceType::Knight,
+        'b' => PieceType::Bishop,
+        'q' => PieceType::Queen,
+        'k' => PieceType::King,
+        _ => return None,
+    };
+    let p_color = if c.is_ascii_uppercase() {
+        PColor::White
+    } else {
+        PColor::Black
+    };
+    Some(Piece { p_type, p_color })
+}
+
+/// FEN letter of a piece: uppercase for White, lowercase for Black.
+pub fn piece_to_char(piece: Piece) -> char {
+    let c = match piece.p_type {
+        PieceType::Pawn => 'p',
+        PieceType::Rook => 'r',
+        PieceType::Knight => 'n',
+        PieceType::Bishop => 'b',
+        PieceType::Queen => 'q',
+        PieceType::King => 'k',
+    };
+    match piece.p_color {
+        PColor::White => c.to_ascii_uppercase(),
+        PColor::Black => c,
+    }
+}
+
+impl Board {
+    /// Builds a board from a FEN string. The halfmove clock and fullmove
+    /// number may be omitted and then default to 0 and 1.
+    pub fn from_fen(fen: &str) -> Result<Board, FenError> {
+        let fields: Vec<&str> = fen.split_whitespace().collect();
+        if fields.len() != 4 && fields.len() != 6 {
+            return Err(FenError::WrongFieldCount(fields.len()));
+        }
+
+        let mut board = Board::empty();
+
+        // Piece placement, from rank 8 down to rank 1
+        let ranks: Vec<&str> = fields[0].split('/').collect();
+        if ranks.len() != 8 {
+            return Err(FenError::WrongRankCount(ranks.len()));
+        }
+        let mut kings = [0; 2];
+        for (index, rank_str) in ranks.iter().enumerate() {
+            let rank = 7 - index;
+            let mut file = 0;
+            for c in rank_str.chars() {
+                if let Some(skip) = c.to_digit(10).filter(|d| (1..=8).contains(d)) {
+                    file += skip as usize;
+                    continue;
+                }
+                let piece = piece_from_char(c).ok_or(FenError::InvalidPiece(c))?;
+                if piece.p_type == PieceType::Pawn && (rank == 0 || rank == 7) && file < 8 {
+                    return Err(FenError::PawnOnBackRank((rank, file)));
+                }
+                if file < 8 {
+                    board.new_piece(piece, rank, file);
+                }
+                if piece.p_type == PieceType::King {
+                    kings[piece.p_color as usize] += 1;
+                }
+                file += 1;
+            }
+            if file != 8 {
+                return Err(FenError::WrongRankLength { rank, length: file });
+            }
+        }
+        for color in [PColor::White, PColor::Black] {
+            if kings[color as usize] != 1 {
+                return Err(FenError::KingCount(color, kings[color as usize]));
+            }
+        }
+
+        board.turn = match fields[1] {
+            "w" => PColor::White,
+            "b" => PColor::Black,
+            other => return Err(FenError::InvalidSideToMove(other.to_string())),
+        };
+
+        board.castling_rights = CastlingRights::none();
+        if fields[2] != "-" {
+            for c in fields[2].chars() {
+                let right = match c {
+                    'K' => &mut board.castling_rights.white_kingside,
+                    'Q' => &mut board.castling_rights.white_queenside,
+                    'k' => &mut board.castling_rights.black_kingside,
+                    'q' => &mut board.castling_rights.black_queenside,
+                    _ => return Err(FenError::InvalidCastling(fields[2].to_string())),
+                };
+                if *right {
+                    return Err(FenError::InvalidCastling(fields[2].to_string()));
+                }
+                *right = true;
+            }
+        }
+
+        board.en_passant = match fields[3] {
+            "-" => None,
+            name => match parse_square(name) {
+                Some(square) if square.0 == 2 || square.0 == 5 => Some(square),
+                _ => return Err(FenError::InvalidEnPassant(name.to_string())),
+            },
+        };
+
+        // The pawn that moved two squares stands in front of the en passant
+        // square, which it passed over from its starting square
+        if let Some((rank, file)) = board.en_passant {
+            let (pawn_rank, start_rank, pushed) = match board.turn {
+                PColor::White => (4, 6, PColor::Black),
+                PColor::Black => (3, 1, PColor::White),
+            };
+            let pawn = Piece {
+                p_type: PieceType::Pawn,
+                p_color: pushed,
+            };
+            if rank != (pawn_rank + start_rank) / 2
+                || board.piece_at((pawn_rank, file)) != Some(pawn)
+                || board.piece_at((rank, file)).is_some()
+                || board.piece_at((start_rank, file)).is_some()
+            {
+                return Err(FenError::ImpossibleEnPassant(fields[3].to_string()));
+            }
+        }
+
+        if board.is_in_check(board.turn.opposite()) {
+            return Err(FenError::OpponentInCheck(board.turn.opposite()));
+        }
+
+        if fields.len() == 6 {
+            board.halfmove_clock = fields[4]
+                .parse()
+                .map_err(|_| FenError::InvalidHalfmoveClock(fields[4].to_string()))?;
+            board.fullmove_number = fields[5]
+                .parse()
+                .ok()
+                .filter(|&n| n > 0)
+                .ok_or_else(|| FenError::InvalidFullmoveNumber(fields[5].to_string()))?;
+        }
+
+        board.reset_history();
+        Ok(board)
+    }
+
+    /// The position as a FEN string.
+    pub fn to_fen(&self) -> String {
+        let mut placement = String::new();
+        for rank in (0..8).rev() {
+            let mut empty = 0;
+            for file in 0..8 {
+                match self.piece_at((rank, file)) {
+                    Some(piece) => {
+                        if empty > 0 {
+                            placement.push_str(&empty.to_string());
+                            empty = 0;
+                        }
+                        placement.push(piece_to_char(piece));
+                    }
+                    None => empty += 1,
+                }
+            }
+            if empty > 0 {
+                placement.push_str(&empty.to_string());
+            }
+            if rank > 0 {
+                placement.push('/');
+            }
+        }
+
+        let turn = match self.turn {
+            PColor::White => "w",
+            PColor::Black => "b",
+        };
+
+        let rights = self.castling_rights;
+        let mut castling: String = [
+            (rights.white_kingside, 'K'),
+            (rights.white_queenside, 'Q'),
+            (rights.black_kingside, 'k'),
+            (rights.black_queenside, 'q'),
+        ]
+        .iter()
+        .filter(|(allowed, _)| *allowed)
+        .map(|(_, c)| *c)
+        .collect();
+        if castling.is_empty() {
+            castling.push('-');
+        }
+
+        let en_passant = match self.en_passant {
+            Some(square) => square_name(square),
+            None => "-".to_string(),
+        };
+
+        format!(
+            "{} {} {} {} {} {}",
+            placement, turn, castling, en_passant, self.halfmove_clock, self.fullmove_number
+        )
+    }
+}
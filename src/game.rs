@@ -0,0 +1,461 @@
+use std::fmt;
+use std::time::{Instant, SystemTime};
+
+use crate::chess::{Board, DrawReason, GameStatus, IllegalMove, Move, PColor};
+use crate::clock::{Clock, TimeControl};
+use crate::fen::{FenError, START_FEN};
+use crate::movelist::MoveList;
+use crate::pgn::{date_tag, GameResult, PgnGame};
+
+/// Why a game ended.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Termination {
+    Checkmate,
+    Stalemate,
+    InsufficientMaterial,
+    /// Claimed by the side to move.
+    ThreefoldRepetition,
+    /// Claimed by the side to move.
+    FiftyMoveRule,
+    FivefoldRepetition,
+    SeventyFiveMoveRule,
+    Timeout,
+    /// A flag fell, but the other side could not have mated.
+    TimeoutVsInsufficientMaterial,
+    Resignation,
+    DrawAgreement,
+    /// Only the result is known, as recorded in a loaded game.
+    Recorded,
+}
+
+/// The result of a finished game and how it came about.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Outcome {
+    pub result: GameResult,
+    pub termination: Termination,
+}
+
+impl Outcome {
+    fn win(winner: PColor, termination: Termination) -> Outcome {
+        let result = match winner {
+            PColor::White => GameResult::WhiteWins,
+            PColor::Black => GameResult::BlackWins,
+        };
+        Outcome {
+            result,
+            termination,
+        }
+    }
+
+    fn draw(termination: Termination) -> Outcome {
+        Outcome {
+            result: GameResult::Draw,
+            termination,
+        }
+    }
+
+    pub fn winner(&self) -> Option<PColor> {
+        match self.result {
+            GameResult::WhiteWins => Some(PColor::White),
+            GameResult::BlackWins => Some(PColor::Black),
+            GameResult::Draw | GameResult::Unknown => None,
+        }
+    }
+}
+
+impl fmt::Display for Outcome {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let winner = self.winner().unwrap_or(PColor::White);
+        match self.termination {
+            Termination::Checkmate => write!(f, "Checkmate, {:?} wins", winner),
+            Termination::Timeout => write!(f, "{:?} wins on time", winner),
+            Termination::Resignation => {
+                write!(f, "{:?} resigns, {:?} wins", winner.opposite(), winner)
+            }
+            Termination::Stalemate => write!(f, "Draw by stalemate"),
+            Termination::InsufficientMaterial => write!(f, "Draw by insufficient material"),
+            Termination::ThreefoldRepetition => write!(f, "Draw claimed by threefold repetition"),
+            Termination::FiftyMoveRule => write!(f, "Draw claimed by the fifty-move rule"),
+            Termination::FivefoldRepetition => write!(f, "Draw by fivefold repetition"),
+            Termination::SeventyFiveMoveRule => write!(f, "Draw by the seventy-five-move rule"),
+            Termination::TimeoutVsInsufficientMaterial => {
+                write!(f, "Draw, out of time but no mating material left")
+            }
+            Termination::DrawAgreement => write!(f, "Draw by agreement"),
+            Termination::Recorded => match self.winner() {
+                Some(winner) => write!(f, "{:?} won", winner),
+                None => write!(f, "Drawn"),
+            },
+        }
+    }
+}
+
+/// Why a `Game` refused an action.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum GameError {
+    GameOver,
+    IllegalMove(IllegalMove),
+    /// Only the player to move may claim a draw.
+    NotYourTurn,
+    /// Moves can only be played in the game's latest position.
+    NotAtLastMove,
+    NoMoveToTakeBack,
+    NoDrawOffered,
+    NoDrawToClaim,
+}
+
+impl fmt::Display for GameError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            GameError::GameOver => write!(f, "the game is over"),
+            GameError::IllegalMove(err) => write!(f, "{}", err),
+            GameError::NotYourTurn => write!(f, "it is not your turn"),
+            GameError::NotAtLastMove => {
+                write!(f, "moves can only be played from the latest position")
+            }
+            GameError::NoMoveToTakeBack => write!(f, "there is no move to take back"),
+            GameError::NoDrawOffered => write!(f, "no draw has been offered"),
+            GameError::NoDrawToClaim => {
+                write!(
+                    f,
+                    "neither threefold repetition nor the fifty-move rule applies"
+                )
+            }
+        }
+    }
+}
+
+impl std::error::Error for GameError {}
+
+impl From<IllegalMove> for GameError {
+    fn from(err: IllegalMove) -> GameError {
+        GameError::IllegalMove(err)
+    }
+}
+
+/// A game between two players: the moves from its starting position, who
+/// plays, the clock and, once it is over, the outcome. The rules of chess
+/// end it on the board; resigning, agreeing or claiming a draw and running
+/// out of time are up to the players.
+///
+/// The board can step back through the moves played to look at earlier
+/// positions. A move played there replaces the moves after it.
+#[derive(Clone)]
+pub struct Game {
+    start: Board,
+    board: Board,
+    moves: MoveList,
+    pub white: String,
+    pub black: String,
+    // Tags of a loaded game that `to_pgn` writes back, such as the event
+    tags: Vec<(String, String)>,
+    clock: Option<Clock>,
+    outcome: Option<Outcome>,
+    // The player who offered a draw, until the opponent answers by moving
+    draw_offer: Option<PColor>,
+}
+
+impl Game {
+    /// A game continuing from `board`, with any moves already played on
+    /// it, and on the clock if there is a time control. The clock starts
+    /// with `start_clock`.
+    pub fn new(board: Board, time_control: Option<TimeControl>) -> Game {
+        let mut start = board.clone();
+        while start.unmake_move().is_some() {}
+        let mut game = Game {
+            start,
+            moves: MoveList::new(&board),
+            board,
+            white: "?".to_string(),
+            black: "?".to_string(),
+            tags: Vec::new(),
+            clock: time_control.map(Clock::new),
+            outcome: None,
+            draw_offer: None,
+        };
+        game.outcome = game.board_outcome();
+        game
+    }
+
+    /// A game read from PGN, with its players and other tags. A result
+    /// the final position does not explain, such as a resignation, still
+    /// ends the game.
+    pub fn from_pgn(pgn: &PgnGame, time_control: Option<TimeControl>) -> Result<Game, FenError> {
+        let mut game = Game::new(pgn.board()?, time_control);
+        game.white = pgn.tag("White").unwrap_or("?").to_string();
+        game.black = pgn.tag("Black").unwrap_or("?").to_string();
+        game.tags = pgn.tags.clone();
+        if game.outcome.is_none() && pgn.result != GameResult::Unknown {
+            game.outcome = Some(Outcome {
+                result: pgn.result,
+                termination: Termination::Recorded,
+            });
+        }
+        Ok(game)
+    }
+
+    /// The position the game started from.
+    pub fn start(&self) -> &Board {
+        &self.start
+    }
+
+    /// The position on show, after `moves().ply()` moves.
+    pub fn board(&self) -> &Board {
+        &self.board
+    }
+
+    pub fn moves(&self) -> &MoveList {
+        &self.moves
+    }
+
+    pub fn clock(&self) -> Option<&Clock> {
+        self.clock.as_ref()
+    }
+
+    pub fn time_control(&self) -> Option<TimeControl> {
+        self.clock.as_ref().map(Clock::control)
+    }
+
+    pub fn outcome(&self) -> Option<Outcome> {
+        self.outcome
+    }
+
+    pub fn is_over(&self) -> bool {
+        self.outcome.is_some()
+    }
+
+    pub fn draw_offer(&self) -> Option<PColor> {
+        self.draw_offer
+    }
+
+    /// The side to move in the game, whichever position is on show.
+    pub fn turn(&self) -> PColor {
+        if (self.moves.len() - self.moves.ply()).is_multiple_of(2) {
+            self.board.turn
+        } else {
+            self.board.turn.opposite()
+        }
+    }
+
+    /// Legal moves in the position on show.
+    pub fn legal_moves(&mut self) -> Vec<Move> {
+        self.board.legal_moves()
+    }
+
+    /// Shows the position after `ply` moves.
+    pub fn go_to(&mut self, ply: usize) {
+        self.moves.go_to(&mut self.board, ply);
+    }
+
+    /// Starts the clock of the side to move.
+    pub fn start_clock(&mut self, now: Instant) {
+        let turn = self.turn();
+        if let Some(clock) = &mut self.clock {
+            if self.outcome.is_none() {
+                clock.start(turn, now);
+            }
+        }
+    }
+
+    /// Plays `mv` in the latest position and presses the clock.
+    pub fn play(&mut self, mv: Move, now: Instant) -> Result<(), GameError> {
+        if self.is_over() {
+            return Err(GameError::GameOver);
+        }
+        if !self.moves.is_at_end() {
+            return Err(GameError::NotAtLastMove);
+        }
+        self.moves.play(&mut self.board, mv)?;
+        // Moving instead of accepting turns a draw offer down
+        if self.draw_offer == Some(self.board.turn) {
+            self.draw_offer = None;
+        }
+        if let Some(clock) = &mut self.clock {
+            if clock.running().is_some() {
+                clock.press(now);
+                if clock.running() != Some(self.board.turn) {
+                    clock.start(self.board.turn, now);
+                }
+            }
+        }
+        self.outcome = self.board_outcome();
+        self.stop_if_over(now);
+        Ok(())
+    }
+
+    /// Takes back the last move of the game. A game that ended on the
+    /// board goes on again, but not one that was resigned, agreed drawn,
+    /// claimed or lost on time.
+    pub fn take_back(&mut self, now: Instant) -> Result<Move, GameError> {
+        if self.outcome.is_some() && self.board_outcome().is_none() {
+            return Err(GameError::GameOver);
+        }
+        self.go_to(self.moves.len());
+        let mv = self
+            .moves
+            .take_back(&mut self.board)
+            .ok_or(GameError::NoMoveToTakeBack)?;
+        self.outcome = self.board_outcome();
+        self.draw_offer = None;
+        if let Some(clock) = &mut self.clock {
+            if self.outcome.is_none() {
+                clock.start(self.board.turn, now);
+            }
+        }
+        Ok(mv)
+    }
+
+    pub fn resign(&mut self, color: PColor, now: Instant) -> Result<(), GameError> {
+        self.finish(
+            Outcome::win(color.opposite(), Termination::Resignation),
+            now,
+        )
+    }
+
+    /// Offers the opponent a draw, which stands until they move.
+    pub fn offer_draw(&mut self, color: PColor) -> Result<(), GameError> {
+        if self.is_over() {
+            return Err(GameError::GameOver);
+        }
+        self.draw_offer = Some(color);
+        Ok(())
+    }
+
+    /// Accepts the draw the opponent of `color` offered.
+    pub fn accept_draw(&mut self, color: PColor, now: Instant) -> Result<(), GameError> {
+        if self.draw_offer != Some(color.opposite()) {
+            return Err(GameError::NoDrawOffered);
+        }
+        self.finish(Outcome::draw(Termination::DrawAgreement), now)
+    }
+
+    /// What the side to move could claim a draw by right now.
+    pub fn claimable_draw(&self) -> Option<Termination> {
+        let mut end = self.board.clone();
+        self.moves.clone().go_to(&mut end, self.moves.len());
+        if end.repetitions() >= 3 {
+            Some(Termination::ThreefoldRepetition)
+        } else if end.halfmove_clock >= 100 {
+            Some(Termination::FiftyMoveRule)
+        } else {
+            None
+        }
+    }
+
+    /// Ends the game in a draw by threefold repetition or the fifty-move
+    /// rule, if `color` is to move and one of them applies.
+    pub fn claim_draw(&mut self, color: PColor, now: Instant) -> Result<(), GameError> {
+        if self.is_over() {
+            return Err(GameError::GameOver);
+        }
+        if color != self.turn() {
+            return Err(GameError::NotYourTurn);
+        }
+        let termination = self.claimable_draw().ok_or(GameError::NoDrawToClaim)?;
+        self.finish(Outcome::draw(termination), now)
+    }
+
+    /// Ends the game if a flag has fallen by `now`, showing the final
+    /// position. Returns whether it did.
+    pub fn check_flag(&mut self, now: Instant) -> bool {
+        if self.is_over() {
+            return false;
+        }
+        let Some(flagged) = self.clock.as_ref().and_then(|clock| clock.flagged(now)) else {
+            return false;
+        };
+        self.go_to(self.moves.len());
+        // Running out of time only loses if the opponent could still mate
+        let winner = flagged.opposite();
+        let outcome = if self.board.has_mating_material(winner) {
+            Outcome::win(winner, Termination::Timeout)
+        } else {
+            Outcome::draw(Termination::TimeoutVsInsufficientMaterial)
+        };
+        self.finish(outcome, now).is_ok()
+    }
+
+    /// The game in PGN, with the players, the time control and how it ended.
+    pub fn to_pgn(&self) -> PgnGame {
+        let mut pgn = PgnGame::new();
+        pgn.set_tag("Date", &date_tag(SystemTime::now()));
+        for (name, value) in &self.tags {
+            pgn.set_tag(name, value);
+        }
+        pgn.set_tag("White", &self.white);
+        pgn.set_tag("Black", &self.black);
+        let fen = self.start.to_fen();
+        if fen != START_FEN {
+            pgn.set_tag("SetUp", "1");
+            pgn.set_tag("FEN", &fen);
+        }
+        pgn.moves = self.moves.moves().to_vec();
+        if let Some(tag) = self.time_control().and_then(time_control_tag) {
+            pgn.set_tag("TimeControl", &tag);
+        }
+        if let Some(outcome) = self.outcome {
+            pgn.set_result(outcome.result);
+            let termination = match outcome.termination {
+                Termination::Timeout | Termination::TimeoutVsInsufficientMaterial => {
+                    Some("time forfeit")
+                }
+                // Whatever the loaded game said is kept
+                Termination::Recorded => None,
+                _ => Some("normal"),
+            };
+            if let Some(termination) = termination {
+                pgn.set_tag("Termination", termination);
+            }
+        } else {
+            pgn.set_result(GameResult::Unknown);
+        }
+        pgn
+    }
+
+    fn finish(&mut self, outcome: Outcome, now: Instant) -> Result<(), GameError> {
+        if self.is_over() {
+            return Err(GameError::GameOver);
+        }
+        self.outcome = Some(outcome);
+        self.stop_if_over(now);
+        Ok(())
+    }
+
+    fn stop_if_over(&mut self, now: Instant) {
+        if self.outcome.is_some() {
+            self.draw_offer = None;
+            if let Some(clock) = &mut self.clock {
+                clock.stop(now);
+            }
+        }
+    }
+
+    // How the rules end the game in its final position, if they do.
+    // Threefold repetition and the fifty-move rule only end it when
+    // claimed, their fivefold and seventy-five move versions always do
+    fn board_outcome(&self) -> Option<Outcome> {
+        let mut end = self.board.clone();
+        self.moves.clone().go_to(&mut end, self.moves.len());
+        Some(match end.game_status() {
+            GameStatus::Checkmate(winner) => Outcome::win(winner, Termination::Checkmate),
+            GameStatus::Stalemate => Outcome::draw(Termination::Stalemate),
+            GameStatus::Draw(DrawReason::InsufficientMaterial) => {
+                Outcome::draw(Termination::InsufficientMaterial)
+            }
+            _ if end.repetitions() >= 5 => Outcome::draw(Termination::FivefoldRepetition),
+            _ if end.halfmove_clock >= 150 => Outcome::draw(Termination::SeventyFiveMoveRule),
+            _ => return None,
+        })
+    }
+}
+
+// The PGN TimeControl tag, "<seconds>" or "<seconds>+<increment>". It has
+// no way to write a delay
+fn time_control_tag(control: TimeControl) -> Option<String> {
+    let base = control.base().as_secs();
+    match control {
+        TimeControl::SuddenDeath { .. } => Some(base.to_string()),
+        TimeControl::Fischer { increment, .. } => Some(format!("{}+{}", base, increment.as_secs())),
+        TimeControl::Bronstein { .. } => None,
+    }
+}
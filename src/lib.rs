@@ -0,0 +1,20 @@
+//! The rules of chess, the engine and its UCI frontend, without the SDL2
+//! window.
+
+pub mod bitboard;
+pub mod chess;
+pub mod clock;
+pub mod eval;
+pub mod fen;
+pub mod game;
+pub mod movelist;
+pub mod perft;
+pub mod pgn;
+pub mod san;
+pub mod search;
+pub mod tt;
+pub mod uci;
+pub mod zobrist;
+
+#[cfg(test)]
+mod test;
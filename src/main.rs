@@ -1,164 +1,549 @@
-pub mod chess;
+mod font;
+mod renderer;
+mod theme;
+
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{mpsc, Arc};
+use std::thread;
+use std::time::{Duration, Instant};
 
 use sdl2::event::Event;
-use sdl2::image::{InitFlag, LoadTexture};
+use sdl2::image::InitFlag;
 use sdl2::keyboard::Keycode;
-use sdl2::pixels::Color;
 
-use crate::chess::{PColor , PieceType};
+use chess_engine::chess::{Move, PColor};
+use chess_engine::clock::TimeControl;
+use chess_engine::game::Game;
+use chess_engine::search::{SearchLimits, SearchResult, Searcher};
+use chess_engine::uci::GoParams;
+use chess_engine::{chess, pgn, uci};
+
+use crate::renderer::{
+    move_list_index_at, promotion_index, square_at, Frame, PieceTextures, Renderer, PANEL_WIDTH,
+    WINDOW_SIZE,
+};
+use crate::theme::{Theme, THEME_NAMES};
+
+// Shortest time between two redraws, about 60 frames per second
+const FRAME_TIME: Duration = Duration::from_millis(16);
+
+// A piece picked up with the mouse, and where the cursor is now
+struct Drag {
+    from: (usize, usize),
+    x: i32,
+    y: i32,
+    // Whether the piece was selected before it was picked up
+    was_selected: bool,
+}
+
+// Value following a `--name` command line option
+fn arg_value<'a>(args: &'a [String], name: &str) -> Option<&'a str> {
+    let index = args.iter().position(|arg| arg == name)?;
+    Some(args.get(index + 1).map(String::as_str).unwrap_or_default())
+}
+
+// Whether `--name` was given on the command line
+fn has_flag(args: &[String], name: &str) -> bool {
+    args.iter().any(|arg| arg == name)
+}
 
 fn main() {
-    let mut board = chess::Board::new();
+    let args: Vec<String> = std::env::args().collect();
 
-    const WINDOW_SIZE: u32 = 1000;
-    const SQUARE_SIZE: u32 = WINDOW_SIZE / BOARD_SIZE;
-    const BOARD_SIZE: u32 = 8;
-    const PIECE_SIZE: u32 = SQUARE_SIZE * 9 / 10;
+    // `uci` (or `--uci`) speaks the UCI protocol on stdin/stdout for chess
+    // GUIs, without opening a window
+    if matches!(args.get(1).map(String::as_str), Some("uci" | "--uci")) {
+        uci::run();
+        return;
+    }
 
-    let mut _moves: Vec<(usize, usize)> = Vec::new();
+    // `--fen "<FEN>"` starts from the given position instead of the usual setup
+    let board = match arg_value(&args, "--fen") {
+        Some(fen) => match chess::Board::from_fen(fen) {
+            Ok(board) => board,
+            Err(err) => {
+                eprintln!("Invalid FEN: {}", err);
+                std::process::exit(1);
+            }
+        },
+        None => chess::Board::new(),
+    };
+
+    // `--pgn <file>` replays a saved game, the first one in the file or
+    // the one picked with `--game <n>`, with its players and result
+    let loaded = arg_value(&args, "--pgn").map(|path| {
+        let number: usize = arg_value(&args, "--game")
+            .and_then(|n| n.parse().ok())
+            .unwrap_or(1);
+        let games = std::fs::read_to_string(path)
+            .map_err(|err| err.to_string())
+            .and_then(|text| pgn::parse_pgn(&text).map_err(|err| err.to_string()));
+        let game = match games {
+            Ok(games) => games.into_iter().nth(number.saturating_sub(1)),
+            Err(err) => {
+                eprintln!("Could not read {}: {}", path, err);
+                std::process::exit(1);
+            }
+        };
+        let Some(game) = game else {
+            eprintln!("{} has no game number {}", path, number);
+            std::process::exit(1);
+        };
+        (path, game)
+    });
+    // `S` saves the game to `--save <file>`, by default game.pgn
+    let save_path = arg_value(&args, "--save").unwrap_or("game.pgn").to_string();
+
+    // The square clicked last and the legal moves of the piece on it
+    let mut selected: Option<(usize, usize)> = None;
+    let mut _moves: Vec<Move> = Vec::new();
+    let mut drag: Option<Drag> = None;
+    // Promotion moves waiting for the player to pick a piece
+    let mut promotion_choices: Vec<Move> = Vec::new();
+
+    // `--engine white|black` lets the computer play that color, thinking
+    // for `--movetime <ms>` per move
+    let mut engine_color = match arg_value(&args, "--engine") {
+        Some("white") => Some(PColor::White),
+        Some("black") => Some(PColor::Black),
+        Some(other) => {
+            eprintln!("--engine must be 'white' or 'black', not '{}'", other);
+            std::process::exit(1);
+        }
+        None => None,
+    };
+    // The board faces the player against the engine, `--flip` turns it
+    // around. `--coordinates` labels the ranks and files
+    let mut flipped = (engine_color == Some(PColor::White)) != has_flag(&args, "--flip");
+    let mut coordinates = has_flag(&args, "--coordinates");
+    // `--theme <name>` picks the board colours
+    let theme = match arg_value(&args, "--theme") {
+        Some(name) => match Theme::named(name) {
+            Some(theme) => theme,
+            None => {
+                eprintln!(
+                    "--theme must be one of {}, not '{}'",
+                    THEME_NAMES.join(", "),
+                    name
+                );
+                std::process::exit(1);
+            }
+        },
+        None => Theme::default(),
+    };
+    // `--time <minutes>[+<increment>|d<delay>]` plays on the clock, e.g.
+    // `--time 5`, `--time 3+2` or `--time 5d3`
+    let time_control = match arg_value(&args, "--time") {
+        Some(control) => match TimeControl::parse(control) {
+            Ok(control) => Some(control),
+            Err(err) => {
+                eprintln!("{}", err);
+                std::process::exit(1);
+            }
+        },
+        None => None,
+    };
+    let mut game = match &loaded {
+        Some((path, pgn)) => match Game::from_pgn(pgn, time_control) {
+            Ok(game) => game,
+            Err(err) => {
+                eprintln!("Invalid FEN in {}: {}", path, err);
+                std::process::exit(1);
+            }
+        },
+        None => {
+            let mut game = Game::new(board, time_control);
+            set_player_names(&mut game, engine_color);
+            game
+        }
+    };
+    let move_time = Duration::from_millis(
+        arg_value(&args, "--movetime")
+            .and_then(|ms| ms.parse().ok())
+            .unwrap_or(1000),
+    );
+    // The search runs on its own thread so the window stays responsive
+    let mut engine_search: Option<(mpsc::Receiver<SearchResult>, Arc<AtomicBool>)> = None;
 
     let sdl_context = sdl2::init().unwrap();
     let video_subsystem = sdl_context.video().unwrap();
     let _image_context = sdl2::image::init(InitFlag::PNG | InitFlag::JPG)
         .expect("Couldn't initialize image context");
     let window = video_subsystem
-        .window("Chess", WINDOW_SIZE, WINDOW_SIZE)
+        .window("Chess", WINDOW_SIZE + PANEL_WIDTH, WINDOW_SIZE)
         .position_centered()
         .build()
         .unwrap();
 
-    let mut canvas = window.into_canvas().build().unwrap();
-
-    canvas.set_draw_color(Color::RGB(255, 255, 255));
-    // canvas.clear();
-    canvas.present();
+    let canvas = window.into_canvas().build().unwrap();
+    // The piece images are loaded once, up front
+    let texture_creator = canvas.texture_creator();
+    let textures = match PieceTextures::load(&texture_creator, Path::new("textures")) {
+        Ok(textures) => textures,
+        Err(err) => {
+            eprintln!("{}", err);
+            std::process::exit(1);
+        }
+    };
+    let mut renderer = Renderer::new(canvas, textures, theme);
 
     let mut event_pump = sdl_context.event_pump().unwrap();
 
+    // Only redraw after something changed, and at most once per frame
+    let mut redraw = true;
+    let mut last_draw = Instant::now() - FRAME_TIME;
+    game.start_clock(Instant::now());
     'running: loop {
-        for event in event_pump.poll_iter() {
+        // Sleep until there is input, but wake up every frame to check on
+        // the engine and to draw
+        let first_event = event_pump.wait_event_timeout(FRAME_TIME.as_millis() as u32);
+        for event in first_event.into_iter().chain(event_pump.poll_iter()) {
+            redraw = true;
+            let now = Instant::now();
             match event {
                 Event::Quit { .. } => break 'running,
                 Event::KeyDown {
                     keycode: Some(Keycode::Escape),
                     ..
                 } => break 'running,
+                // Take back the last move
+                Event::KeyDown {
+                    keycode: Some(Keycode::Backspace),
+                    ..
+                } => {
+                    if let Some((_, stop)) = engine_search.take() {
+                        stop.store(true, Ordering::Relaxed);
+                    }
+                    match game.take_back(now) {
+                        // Against the engine, take back its reply too
+                        Ok(_) if engine_color == Some(game.turn()) => {
+                            let _ = game.take_back(now);
+                        }
+                        Ok(_) => {}
+                        Err(err) => eprintln!("Cannot take back: {}", err),
+                    }
+                    selected = None;
+                    _moves.clear();
+                    promotion_choices.clear();
+                }
+                // Resign for the player
+                Event::KeyDown {
+                    keycode: Some(Keycode::R),
+                    ..
+                } => {
+                    let player = engine_color.map_or(game.turn(), PColor::opposite);
+                    if let Err(err) = game.resign(player, now) {
+                        eprintln!("Cannot resign: {}", err);
+                    }
+                }
+                // Draw: claim it if the rules allow, accept the opponent's
+                // offer, or else offer one
+                Event::KeyDown {
+                    keycode: Some(Keycode::D),
+                    ..
+                } => {
+                    let player = engine_color.map_or(game.turn(), PColor::opposite);
+                    let answer = if game.claimable_draw().is_some() {
+                        game.claim_draw(player, now)
+                    } else if game.draw_offer() == Some(player.opposite()) {
+                        game.accept_draw(player, now)
+                    } else {
+                        game.offer_draw(player)
+                    };
+                    if let Err(err) = answer {
+                        eprintln!("No draw: {}", err);
+                    }
+                }
+                // Switch the engine on for the side to move, or off again
+                Event::KeyDown {
+                    keycode: Some(Keycode::E),
+                    ..
+                } => {
+                    if engine_color.is_some() {
+                        engine_color = None;
+                        if let Some((_, stop)) = engine_search.take() {
+                            stop.store(true, Ordering::Relaxed);
+                        }
+                    } else {
+                        engine_color = Some(game.turn());
+                        // Turn the board towards the player
+                        flipped = game.turn() == PColor::White;
+                        selected = None;
+                        _moves.clear();
+                        promotion_choices.clear();
+                    }
+                    set_player_names(&mut game, engine_color);
+                }
+                // Turn the board around
+                Event::KeyDown {
+                    keycode: Some(Keycode::F),
+                    ..
+                } => flipped = !flipped,
+                // Show or hide the rank and file labels
+                Event::KeyDown {
+                    keycode: Some(Keycode::C),
+                    ..
+                } => coordinates = !coordinates,
+                // Step through the game, keeping the later moves
+                Event::KeyDown {
+                    keycode:
+                        Some(key @ (Keycode::Left | Keycode::Right | Keycode::Home | Keycode::End)),
+                    ..
+                } => {
+                    let ply = match key {
+                        Keycode::Left => game.moves().ply().saturating_sub(1),
+                        Keycode::Right => game.moves().ply() + 1,
+                        Keycode::Home => 0,
+                        _ => game.moves().len(),
+                    };
+                    if let Some((_, stop)) = engine_search.take() {
+                        stop.store(true, Ordering::Relaxed);
+                    }
+                    game.go_to(ply);
+                    selected = None;
+                    _moves.clear();
+                    promotion_choices.clear();
+                }
+                // Clicking a move in the list shows the position after it
+                Event::MouseButtonDown { x, y, .. } if x >= WINDOW_SIZE as i32 => {
+                    let has_clock = game.clock().is_some();
+                    if let Some(index) = move_list_index_at(game.moves(), has_clock, x, y) {
+                        if let Some((_, stop)) = engine_search.take() {
+                            stop.store(true, Ordering::Relaxed);
+                        }
+                        game.go_to(index + 1);
+                        selected = None;
+                        _moves.clear();
+                        promotion_choices.clear();
+                    }
+                }
+                // Save the game so far as PGN
+                Event::KeyDown {
+                    keycode: Some(Keycode::S),
+                    ..
+                } => {
+                    let written = game
+                        .to_pgn()
+                        .to_pgn()
+                        .map_err(|err| err.to_string())
+                        .and_then(|text| {
+                            std::fs::write(&save_path, text).map_err(|err| err.to_string())
+                        });
+                    match written {
+                        Ok(()) => println!("Saved the game to {}", save_path),
+                        Err(err) => eprintln!("Could not save {}: {}", save_path, err),
+                    }
+                }
+                // No more moves once the game is over, while the engine is to
+                // move or while an earlier position is on show
+                Event::MouseButtonDown { .. }
+                    if game.is_over()
+                        || engine_color == Some(game.turn())
+                        || !game.moves().is_at_end() => {}
                 Event::MouseButtonDown { x, y, .. } => {
                     // Get the rank and file of the square that was clicked
-                    let file = x as u32 / SQUARE_SIZE;
-                    let rank = 7 - (y as u32 / SQUARE_SIZE);
+                    let Some(square) = square_at(x, y, flipped) else {
+                        continue;
+                    };
 
-                    if let Some((selected_rank, selected_file)) = board.selected_piece {
-                        if let Some(piece) = board.squares[selected_rank][selected_file] {
-                            if piece.p_color != board.turn {
-                                continue;
-                            }
+                    // A promotion is pending: the click either picks a piece or cancels
+                    if !promotion_choices.is_empty() {
+                        if let Some(index) = promotion_index(promotion_choices[0], square) {
+                            let _ = game.play(promotion_choices[index], now);
                         }
-                        //check if the move is valid
-                        if (rank as usize, file as usize) != (selected_rank, selected_file)
-                            && _moves.contains(&(rank as usize, file as usize))
-                        {
-                            // Move the piece to the new square
-                            let piece_to_move = board.squares[selected_rank][selected_file].take();
-                            //check for en passant
-                            if piece_to_move.unwrap().p_type == PieceType::Pawn
-                                && selected_file != file as usize
-                                && board.squares[rank as usize][file as usize].is_none()
-                            {
-                                if board.turn == PColor::White {
-                                    board.squares[rank as usize - 1][file as usize] = None;
-                                } else {
-                                    board.squares[rank as usize + 1][file as usize] = None;
-                                }
-                            }
-                            board.squares[rank as usize][file as usize] = piece_to_move;
-
-                            board.selected_piece = Some((rank as usize, file as usize));
+                        promotion_choices.clear();
+                        selected = None;
+                        _moves.clear();
+                        continue;
+                    }
 
-                            //clear the moves
-                            _moves.clear();
+                    // Clicking a square the selected piece can go to plays the move
+                    if play_selected(
+                        &mut game,
+                        &mut selected,
+                        &mut _moves,
+                        &mut promotion_choices,
+                        square,
+                    ) {
+                        continue;
+                    }
 
-                            // Deselect the piece
-                            board.selected_piece = None;
-
-                            //Change the turn
-                            board.turn = match board.turn {
-                                PColor::White => PColor::Black,
-                                PColor::Black => PColor::White,
-                            };
-                        } else {
-                            // Deselect the piece if the same square is clicked and clear the moves
-                            board.selected_piece = None;
+                    // Pressing on a piece of the side to move selects it and
+                    // picks it up, anywhere else deselects
+                    match game.board().piece_at(square) {
+                        Some(piece) if piece.p_color == game.board().turn() => {
+                            drag = Some(Drag {
+                                from: square,
+                                x,
+                                y,
+                                was_selected: selected == Some(square),
+                            });
+                            selected = Some(square);
+                            _moves = game
+                                .legal_moves()
+                                .into_iter()
+                                .filter(|mv| mv.from == square)
+                                .collect();
+                        }
+                        _ => {
+                            selected = None;
                             _moves.clear();
                         }
-                    } else {
-                        //check if the clicked piece is the same color as the turn
-                        if let Some(piece) = board.squares[rank as usize][file as usize] {
-                            if piece.p_color != board.turn {
-                                continue;
+                    }
+                }
+                // The dragged piece follows the cursor
+                Event::MouseMotion { x, y, .. } => {
+                    if let Some(drag) = &mut drag {
+                        drag.x = x;
+                        drag.y = y;
+                    }
+                }
+                Event::MouseButtonUp { x, y, .. } => {
+                    let Some(drag) = drag.take() else {
+                        continue;
+                    };
+                    match square_at(x, y, flipped) {
+                        // Let go where it was picked up: a click, which keeps the
+                        // piece selected for click-to-move, or unselects it when
+                        // it was selected already
+                        Some(square) if square == drag.from => {
+                            if drag.was_selected {
+                                selected = None;
+                                _moves.clear();
                             }
                         }
-                        // Select the square if no piece is currently selected
-                        board.selected_piece = Some((rank as usize, file as usize));
-                        _moves = board.legal_moves();
+                        // Dropped on a square it can go to
+                        Some(square)
+                            if play_selected(
+                                &mut game,
+                                &mut selected,
+                                &mut _moves,
+                                &mut promotion_choices,
+                                square,
+                            ) => {}
+                        // Anywhere else it goes back where it came from
+                        _ => {
+                            selected = None;
+                            _moves.clear();
+                        }
                     }
                 }
                 _ => {}
             }
         }
 
-        // loop to iterate through the 2d array
-        for rank in 0..BOARD_SIZE {
-            for file in 0..BOARD_SIZE {
-                let x = file * SQUARE_SIZE;
-                let y = (BOARD_SIZE - rank - 1) * SQUARE_SIZE;
-
-                let square = sdl2::rect::Rect::new(x as i32, y as i32, SQUARE_SIZE, SQUARE_SIZE);
-
-                let color = if board.selected_piece == Some((rank as usize, file as usize)) {
-                    sdl2::pixels::Color::RGB(172, 172, 172) // Light Square
-                } else if _moves.contains(&(rank as usize, file as usize)) {
-                    sdl2::pixels::Color::RGB(172, 172, 172) // Light blue for selected
-                } else if (rank + file) % 2 == 0 {
-                    sdl2::pixels::Color::RGB(101, 97, 92) // dark blue for valid moves
-                } else {
-                    sdl2::pixels::Color::RGB(198, 193, 170) // Dark square
-                };
-
-                canvas.set_draw_color(color);
-                canvas.fill_rect(square).unwrap();
-
-                if let Some(piece) = board.squares[rank as usize][file as usize] {
-                    let piece_rect = sdl2::rect::Rect::new(
-                        (x + (SQUARE_SIZE - PIECE_SIZE) / 2) as i32,
-                        (y + (SQUARE_SIZE - PIECE_SIZE) / 2) as i32,
-                        PIECE_SIZE,
-                        PIECE_SIZE,
-                    );
-
-                    let texture_string = format!(
-                        "textures/{}-{}.png",
-                        format!("{:?}", piece.p_type)
-                            .split("::")
-                            .last()
-                            .unwrap()
-                            .to_lowercase(),
-                        format!("{:?}", piece.p_color)
-                            .split("::")
-                            .last()
-                            .unwrap()
-                            .to_lowercase()
-                    );
-                    let texture_creator = canvas.texture_creator();
-                    let texture = texture_creator.load_texture(texture_string).unwrap();
-
-                    canvas
-                        .copy(&texture, None, piece_rect)
-                        .expect("Failed to copy image.");
+        // A fallen flag ends the game, shown from its final position
+        if game.check_flag(Instant::now()) {
+            if let Some((_, stop)) = engine_search.take() {
+                stop.store(true, Ordering::Relaxed);
+            }
+            selected = None;
+            _moves.clear();
+            promotion_choices.clear();
+        }
+        if game.clock().is_some_and(|clock| clock.running().is_some()) {
+            redraw = true;
+        }
+
+        // Let the engine think when it is its turn, and play its move once
+        // found. Not while looking at an earlier position
+        if engine_color == Some(game.turn()) && !game.is_over() && game.moves().is_at_end() {
+            match &engine_search {
+                None => {
+                    let (sender, receiver) = mpsc::channel();
+                    // On the clock the engine budgets its own time like in UCI
+                    let limits = match game.clock() {
+                        Some(clock) => {
+                            let now = Instant::now();
+                            let increment = Some(clock.control().increment());
+                            GoParams {
+                                wtime: Some(clock.remaining(PColor::White, now)),
+                                btime: Some(clock.remaining(PColor::Black, now)),
+                                winc: increment,
+                                binc: increment,
+                                ..GoParams::default()
+                            }
+                            .limits(game.turn())
+                        }
+                        None => SearchLimits::time(move_time),
+                    };
+                    let mut searcher = Searcher::new(limits);
+                    let stop = searcher.stop.clone();
+                    let mut engine_board = game.board().clone();
+                    thread::spawn(move || {
+                        let _ = sender.send(searcher.search(&mut engine_board));
+                    });
+                    engine_search = Some((receiver, stop));
+                }
+                Some((receiver, _)) => {
+                    if let Ok(result) = receiver.try_recv() {
+                        if let Some(mv) = result.best_move {
+                            if let Err(err) = game.play(mv, Instant::now()) {
+                                eprintln!("Engine move rejected: {}", err);
+                            }
+                            redraw = true;
+                        }
+                        engine_search = None;
+                    }
                 }
             }
         }
 
-        canvas.present();
+        if redraw && last_draw.elapsed() >= FRAME_TIME {
+            renderer.draw(&Frame {
+                board: game.board(),
+                selected,
+                targets: &_moves,
+                promotion: promotion_choices.first().copied(),
+                dragging: drag.as_ref().map(|drag| (drag.from, (drag.x, drag.y))),
+                move_list: game.moves(),
+                outcome: game.outcome(),
+                draw_offer: game.draw_offer(),
+                flipped,
+                coordinates,
+                clock: game.clock(),
+            });
+            redraw = false;
+            last_draw = Instant::now();
+        }
+    }
+}
+
+// Names the players for the saved game
+fn set_player_names(game: &mut Game, engine_color: Option<PColor>) {
+    let name = |color| {
+        if engine_color == Some(color) {
+            "ChessEngine"
+        } else {
+            "Player"
+        }
+    };
+    game.white = name(PColor::White).to_string();
+    game.black = name(PColor::Black).to_string();
+}
+
+// Plays the selected piece's move to `to`, or opens the promotion picker
+// when several moves go there. Returns false if none does, or the move
+// turns out not to be legal
+fn play_selected(
+    game: &mut Game,
+    selected: &mut Option<(usize, usize)>,
+    targets: &mut Vec<Move>,
+    promotion_choices: &mut Vec<Move>,
+    to: (usize, usize),
+) -> bool {
+    let candidates: Vec<Move> = targets.iter().filter(|mv| mv.to == to).copied().collect();
+    match candidates.len() {
+        0 => return false,
+        1 => {
+            let played = game.play(candidates[0], Instant::now()).is_ok();
+            *selected = None;
+            targets.clear();
+            return played;
+        }
+        // Several moves to one square means a promotion, let the player choose
+        _ => *promotion_choices = candidates,
     }
+    true
 }
@@ -0,0 +1,144 @@
+use crate::chess::{Board, IllegalMove, Move, PColor};
+
+/// The moves of a game in SAN together with the ply being looked at, so
+/// the board can step back and forth without forgetting later moves.
+/// The board's history always holds exactly the moves before that ply.
+#[derive(Clone, Debug)]
+pub struct MoveList {
+    moves: Vec<Move>,
+    sans: Vec<String>,
+    ply: usize,
+    first_move_number: u32,
+    // A game set up with Black to move has no White move in its first row
+    black_starts: bool,
+}
+
+impl MoveList {
+    /// The moves already played on `board`, looking at the last one.
+    pub fn new(board: &Board) -> MoveList {
+        let moves: Vec<Move> = board.history().iter().map(|entry| entry.mv).collect();
+        let mut replay = board.clone();
+        while replay.unmake_move().is_some() {}
+        let first_move_number = replay.fullmove_number;
+        let black_starts = replay.turn == PColor::Black;
+        let sans = moves
+            .iter()
+            .map(|mv| {
+                let san = replay.move_to_san(mv);
+                replay.make_move(*mv);
+                san
+            })
+            .collect();
+        MoveList {
+            ply: moves.len(),
+            moves,
+            sans,
+            first_move_number,
+            black_starts,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.moves.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.moves.is_empty()
+    }
+
+    /// Number of moves played on the board, 0 at the start of the game.
+    pub fn ply(&self) -> usize {
+        self.ply
+    }
+
+    pub fn is_at_end(&self) -> bool {
+        self.ply == self.moves.len()
+    }
+
+    pub fn moves(&self) -> &[Move] {
+        &self.moves
+    }
+
+    pub fn sans(&self) -> &[String] {
+        &self.sans
+    }
+
+    /// The moves laid out by move number: the number and the indices of
+    /// White's and Black's move in `sans`.
+    pub fn rows(&self) -> Vec<(u32, Option<usize>, Option<usize>)> {
+        let offset = usize::from(self.black_starts);
+        let slots = self.moves.len() + offset;
+        (0..slots.div_ceil(2))
+            .map(|row| {
+                let index = |slot: usize| {
+                    (slot >= offset && slot - offset < self.moves.len()).then(|| slot - offset)
+                };
+                (
+                    self.first_move_number + row as u32,
+                    index(2 * row),
+                    index(2 * row + 1),
+                )
+            })
+            .collect()
+    }
+
+    /// Plays `mv` on `board` if it is legal. Moves after the current ply
+    /// are dropped, as the game now goes another way.
+    pub fn play(&mut self, board: &mut Board, mv: Move) -> Result<(), IllegalMove> {
+        // Only legal moves have a SAN, written from the position before them
+        let san = board
+            .is_legal(&mv)
+            .then(|| board.move_to_san(&mv))
+            .ok_or(IllegalMove(mv))?;
+        board.make_move(mv);
+
+        self.moves.truncate(self.ply);
+        self.sans.truncate(self.ply);
+        self.sans.push(san);
+        self.moves.push(mv);
+        self.ply += 1;
+        Ok(())
+    }
+
+    /// Takes back the move before the current ply for good, along with
+    /// anything after it.
+    pub fn take_back(&mut self, board: &mut Board) -> Option<Move> {
+        let mv = board.unmake_move()?;
+        self.ply -= 1;
+        self.moves.truncate(self.ply);
+        self.sans.truncate(self.ply);
+        Some(mv)
+    }
+
+    /// Steps one move back, keeping it to step forward again.
+    pub fn back(&mut self, board: &mut Board) -> bool {
+        if self.ply == 0 {
+            return false;
+        }
+        board.unmake_move();
+        self.ply -= 1;
+        true
+    }
+
+    pub fn forward(&mut self, board: &mut Board) -> bool {
+        match self.moves.get(self.ply) {
+            Some(&mv) => {
+                board.make_move(mv);
+                self.ply += 1;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Shows the position after `ply` moves.
+    pub fn go_to(&mut self, board: &mut Board, ply: usize) {
+        let ply = ply.min(self.moves.len());
+        while self.ply > ply {
+            self.back(board);
+        }
+        while self.ply < ply {
+            self.forward(board);
+        }
+    }
+}
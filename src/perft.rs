@@ -0,0 +1,58 @@
+use crate::chess::{Board, Move};
+
+impl Board {
+    /// Counts the leaf nodes of the legal move tree `depth` plies deep.
+    /// Comparing against published counts catches move generation bugs.
+    pub fn perft(&mut self, depth: u32) -> u64 {
+        if depth == 0 {
+            return 1;
+        }
+        let moves = self.legal_moves();
+        // No need to play the last ply, every legal move is one leaf
+        if depth == 1 {
+            return moves.len() as u64;
+        }
+        let mut nodes = 0;
+        for mv in moves {
+            self.make_move(mv);
+            nodes += self.perft(depth - 1);
+            self.unmake_move();
+        }
+        nodes
+    }
+
+    /// `perft` split up by the first move, to narrow down which move
+    /// leads to a wrong count.
+    pub fn perft_divide(&mut self, depth: u32) -> Vec<(Move, u64)> {
+        if depth == 0 {
+            return Vec::new();
+        }
+        let mut divide = Vec::new();
+        for mv in self.legal_moves() {
+            self.make_move(mv);
+            divide.push((mv, self.perft(depth - 1)));
+            self.unmake_move();
+        }
+        divide
+    }
+}
+
+/// Runs `perft_divide` and prints one line per move and the total, in
+/// the format most engines use so the output can be diffed.
+pub fn run(board: &mut Board, depth: u32) {
+    let start = std::time::Instant::now();
+    let divide = board.perft_divide(depth);
+    let mut total = 0;
+    for (mv, nodes) in &divide {
+        println!("{}: {}", mv, nodes);
+        total += nodes;
+    }
+    let elapsed = start.elapsed();
+    println!();
+    println!("Nodes searched: {}", total);
+    println!(
+        "Time: {} ms ({:.0} nodes/s)",
+        elapsed.as_millis(),
+        total as f64 / elapsed.as_secs_f64().max(1e-9)
+    );
+}
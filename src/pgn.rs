@@ -0,0 +1,476 @@
+use std::fmt;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::chess::{Board, GameStatus, Move, PColor};
+use crate::fen::{FenError, START_FEN};
+use crate::san::SanError;
+
+/// Tags every PGN game carries, in the order they are written.
+pub const SEVEN_TAG_ROSTER: [&str; 7] =
+    ["Event", "Site", "Date", "Round", "White", "Black", "Result"];
+
+// Lines of movetext are kept below this length
+const LINE_WIDTH: usize = 80;
+
+/// Outcome of a game as written in PGN.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum GameResult {
+    WhiteWins,
+    BlackWins,
+    Draw,
+    /// Still going, abandoned or unknown: "*".
+    Unknown,
+}
+
+impl GameResult {
+    pub fn from_status(status: GameStatus) -> GameResult {
+        match status {
+            GameStatus::Checkmate(PColor::White) => GameResult::WhiteWins,
+            GameStatus::Checkmate(PColor::Black) => GameResult::BlackWins,
+            GameStatus::Stalemate | GameStatus::Draw(_) => GameResult::Draw,
+            GameStatus::Ongoing => GameResult::Unknown,
+        }
+    }
+
+    fn parse(token: &str) -> Option<GameResult> {
+        match token {
+            "1-0" => Some(GameResult::WhiteWins),
+            "0-1" => Some(GameResult::BlackWins),
+            "1/2-1/2" => Some(GameResult::Draw),
+            "*" => Some(GameResult::Unknown),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for GameResult {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            GameResult::WhiteWins => "1-0",
+            GameResult::BlackWins => "0-1",
+            GameResult::Draw => "1/2-1/2",
+            GameResult::Unknown => "*",
+        })
+    }
+}
+
+/// Why a PGN file could not be read. `game` counts from 1.
+#[derive(Clone, Debug, PartialEq)]
+pub enum PgnError {
+    Syntax {
+        game: usize,
+        message: String,
+    },
+    InvalidFen {
+        game: usize,
+        error: FenError,
+    },
+    /// `ply` counts half moves from the start of the game, from 1.
+    IllegalMove {
+        game: usize,
+        ply: usize,
+        san: String,
+        error: SanError,
+    },
+}
+
+impl fmt::Display for PgnError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PgnError::Syntax { game, message } => write!(f, "game {}: {}", game, message),
+            PgnError::InvalidFen { game, error } => {
+                write!(f, "game {}: invalid FEN tag: {}", game, error)
+            }
+            PgnError::IllegalMove {
+                game,
+                ply,
+                san,
+                error,
+            } => write!(
+                f,
+                "game {}: move '{}' at ply {} cannot be played: {}",
+                game, san, ply, error
+            ),
+        }
+    }
+}
+
+impl std::error::Error for PgnError {}
+
+/// A game as stored in PGN: its tags, the moves from the starting position
+/// and the result.
+#[derive(Clone, Debug, PartialEq)]
+pub struct PgnGame {
+    /// Name and value pairs, the seven-tag roster first.
+    pub tags: Vec<(String, String)>,
+    pub moves: Vec<Move>,
+    pub result: GameResult,
+}
+
+impl Default for PgnGame {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl PgnGame {
+    /// An empty game with the seven-tag roster filled with unknowns.
+    pub fn new() -> PgnGame {
+        let mut game = PgnGame {
+            tags: Vec::new(),
+            moves: Vec::new(),
+            result: GameResult::Unknown,
+        };
+        for name in SEVEN_TAG_ROSTER {
+            let value = match name {
+                "Date" => "????.??.??",
+                "Result" => "*",
+                _ => "?",
+            };
+            game.set_tag(name, value);
+        }
+        game
+    }
+
+    /// The game played on `board` so far, dated today. The result is
+    /// filled in if the game is over.
+    pub fn from_board(board: &Board) -> PgnGame {
+        let mut game = PgnGame::new();
+        game.set_tag("Date", &date_tag(SystemTime::now()));
+
+        let mut start = board.clone();
+        while start.unmake_move().is_some() {}
+        let fen = start.to_fen();
+        if fen != START_FEN {
+            game.set_tag("SetUp", "1");
+            game.set_tag("FEN", &fen);
+        }
+
+        game.moves = board.history().iter().map(|entry| entry.mv).collect();
+        let mut end = board.clone();
+        game.set_result(GameResult::from_status(end.game_status()));
+        game
+    }
+
+    pub fn tag(&self, name: &str) -> Option<&str> {
+        self.tags
+            .iter()
+            .find(|(tag, _)| tag == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// Sets a tag, replacing an earlier value.
+    pub fn set_tag(&mut self, name: &str, value: &str) {
+        match self.tags.iter_mut().find(|(tag, _)| tag == name) {
+            Some((_, old)) => *old = value.to_string(),
+            None => self.tags.push((name.to_string(), value.to_string())),
+        }
+    }
+
+    /// Sets the result along with its tag.
+    pub fn set_result(&mut self, result: GameResult) {
+        self.result = result;
+        self.set_tag("Result", &result.to_string());
+    }
+
+    /// The position the game starts from, given by the FEN tag if any.
+    pub fn start_board(&self) -> Result<Board, FenError> {
+        match self.tag("FEN") {
+            Some(fen) => Board::from_fen(fen),
+            None => Ok(Board::new()),
+        }
+    }
+
+    /// The final position, with every move in the board's history.
+    pub fn board(&self) -> Result<Board, FenError> {
+        let mut board = self.start_board()?;
+        for &mv in &self.moves {
+            board.make_move(mv);
+        }
+        Ok(board)
+    }
+
+    /// The game in PGN export format.
+    pub fn to_pgn(&self) -> Result<String, FenError> {
+        let mut pgn = String::new();
+        // The roster goes first and in its own order, then any other tags
+        let roster = SEVEN_TAG_ROSTER
+            .iter()
+            .filter_map(|&name| self.tags.iter().find(|(tag, _)| tag == name));
+        let others = self
+            .tags
+            .iter()
+            .filter(|(name, _)| !SEVEN_TAG_ROSTER.contains(&name.as_str()));
+        for (name, value) in roster.chain(others) {
+            let value = value.replace('\\', "\\\\").replace('"', "\\\"");
+            pgn.push_str(&format!("[{} \"{}\"]\n", name, value));
+        }
+        pgn.push('\n');
+
+        let mut board = self.start_board()?;
+        let mut tokens = Vec::new();
+        for (index, &mv) in self.moves.iter().enumerate() {
+            if board.turn == PColor::White {
+                tokens.push(format!("{}.", board.fullmove_number));
+            } else if index == 0 {
+                tokens.push(format!("{}...", board.fullmove_number));
+            }
+            tokens.push(board.move_to_san(&mv));
+            board.make_move(mv);
+        }
+        tokens.push(self.result.to_string());
+
+        let mut line = String::new();
+        for token in tokens {
+            if !line.is_empty() && line.len() + 1 + token.len() >= LINE_WIDTH {
+                pgn.push_str(&line);
+                pgn.push('\n');
+                line.clear();
+            }
+            if !line.is_empty() {
+                line.push(' ');
+            }
+            line.push_str(&token);
+        }
+        pgn.push_str(&line);
+        pgn.push('\n');
+        Ok(pgn)
+    }
+}
+
+/// "YYYY.MM.DD" for the UTC day of `time`.
+pub fn date_tag(time: SystemTime) -> String {
+    let days = time
+        .duration_since(UNIX_EPOCH)
+        .map(|since| since.as_secs() / 86_400)
+        .unwrap_or(0) as i64;
+    // Days since 1970-01-01 to a civil date, after Howard Hinnant
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let day_of_era = z - era * 146_097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    format!("{:04}.{:02}.{:02}", year, month, day)
+}
+
+#[derive(Debug, PartialEq)]
+enum Token {
+    Tag(String, String),
+    VariationStart,
+    VariationEnd,
+    Result(GameResult),
+    // A move number or a move
+    Symbol(String),
+}
+
+// Splits PGN text into `tokens`, dropping comments, NAGs and escaped
+// lines. The tokens before a syntax error are kept, so that the error can
+// be put down to the game it is in
+fn tokenize(text: &str, tokens: &mut Vec<Token>) -> Result<(), String> {
+    let mut chars = text.chars().peekable();
+    let mut line_start = true;
+    while let Some(c) = chars.next() {
+        let at_line_start = line_start;
+        line_start = c == '\n';
+        match c {
+            c if c.is_whitespace() => {}
+            '%' if at_line_start => {
+                chars.by_ref().find(|&c| c == '\n');
+                line_start = true;
+            }
+            ';' => {
+                chars.by_ref().find(|&c| c == '\n');
+                line_start = true;
+            }
+            '{' => {
+                if !chars.by_ref().any(|c| c == '}') {
+                    return Err("comment is never closed".to_string());
+                }
+            }
+            '(' => tokens.push(Token::VariationStart),
+            ')' => tokens.push(Token::VariationEnd),
+            '$' => while chars.next_if(|c| c.is_ascii_digit()).is_some() {},
+            '[' => {
+                let mut name = String::new();
+                while let Some(c) = chars.next_if(|c| !c.is_whitespace() && *c != '"') {
+                    name.push(c);
+                }
+                while chars.next_if(|c| c.is_whitespace()).is_some() {}
+                if chars.next() != Some('"') {
+                    return Err(format!("tag '{}' has no quoted value", name));
+                }
+                let mut value = String::new();
+                loop {
+                    match chars.next() {
+                        Some('\\') => value.extend(chars.next()),
+                        Some('"') => break,
+                        Some(c) => value.push(c),
+                        None => return Err(format!("value of tag '{}' is never closed", name)),
+                    }
+                }
+                while chars.next_if(|c| c.is_whitespace()).is_some() {}
+                if chars.next() != Some(']') {
+                    return Err(format!("tag '{}' is not closed with ']'", name));
+                }
+                tokens.push(Token::Tag(name, value));
+            }
+            _ => {
+                let mut symbol = c.to_string();
+                while let Some(c) =
+                    chars.next_if(|c| !c.is_whitespace() && !"{}()[];$".contains(*c))
+                {
+                    symbol.push(c);
+                }
+                tokens.push(match GameResult::parse(&symbol) {
+                    Some(result) => Token::Result(result),
+                    None => Token::Symbol(symbol),
+                });
+            }
+        }
+    }
+    Ok(())
+}
+
+// A game being read, with the board its moves are replayed on
+struct GameReader {
+    game: PgnGame,
+    board: Option<Board>,
+    variation_depth: usize,
+    has_movetext: bool,
+}
+
+impl GameReader {
+    fn new() -> GameReader {
+        GameReader {
+            game: PgnGame {
+                tags: Vec::new(),
+                moves: Vec::new(),
+                result: GameResult::Unknown,
+            },
+            board: None,
+            variation_depth: 0,
+            has_movetext: false,
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.game.tags.is_empty() && !self.has_movetext
+    }
+
+    fn play(&mut self, number: usize, san: &str) -> Result<(), PgnError> {
+        if self.board.is_none() {
+            let board = self
+                .game
+                .start_board()
+                .map_err(|error| PgnError::InvalidFen {
+                    game: number,
+                    error,
+                })?;
+            self.board = Some(board);
+        }
+        let board = self.board.as_mut().unwrap();
+        let mv = board
+            .parse_san(san)
+            .map_err(|error| PgnError::IllegalMove {
+                game: number,
+                ply: self.game.moves.len() + 1,
+                san: san.to_string(),
+                error,
+            })?;
+        board.make_move(mv);
+        self.game.moves.push(mv);
+        Ok(())
+    }
+
+    fn finish(mut self, number: usize) -> Result<PgnGame, PgnError> {
+        if self.variation_depth > 0 {
+            return Err(PgnError::Syntax {
+                game: number,
+                message: "variation is never closed".to_string(),
+            });
+        }
+        // The FEN is checked even for a game without moves
+        self.game
+            .start_board()
+            .map_err(|error| PgnError::InvalidFen {
+                game: number,
+                error,
+            })?;
+        if !self.has_movetext {
+            if let Some(result) = self.game.tag("Result").and_then(GameResult::parse) {
+                self.game.result = result;
+            }
+        }
+        Ok(self.game)
+    }
+}
+
+/// Reads every game in a PGN file. Comments, NAGs and variations are
+/// skipped; each game is replayed and the first illegal move is reported.
+pub fn parse_pgn(text: &str) -> Result<Vec<PgnGame>, PgnError> {
+    let mut games = Vec::new();
+    let mut tokens = Vec::new();
+    let syntax_error = tokenize(text, &mut tokens).err();
+    let mut reader = GameReader::new();
+
+    for token in tokens {
+        let number = games.len() + 1;
+        match token {
+            // Tags after movetext belong to the next game, for files where
+            // a game is missing its result
+            Token::Tag(name, value) => {
+                if reader.has_movetext {
+                    games.push(std::mem::replace(&mut reader, GameReader::new()).finish(number)?);
+                }
+                reader.game.tags.push((name, value));
+            }
+            Token::VariationStart => {
+                reader.has_movetext = true;
+                reader.variation_depth += 1;
+            }
+            Token::VariationEnd => {
+                if reader.variation_depth == 0 {
+                    return Err(PgnError::Syntax {
+                        game: number,
+                        message: "')' without a variation to close".to_string(),
+                    });
+                }
+                reader.variation_depth -= 1;
+            }
+            Token::Result(_) if reader.variation_depth > 0 => {}
+            Token::Result(result) => {
+                reader.has_movetext = true;
+                reader.game.result = result;
+                games.push(std::mem::replace(&mut reader, GameReader::new()).finish(number)?);
+            }
+            Token::Symbol(_) if reader.variation_depth > 0 => {}
+            Token::Symbol(symbol) => {
+                reader.has_movetext = true;
+                // Move numbers like "12." or "12...", possibly glued to the move
+                let san = match symbol.rfind('.') {
+                    Some(index) => &symbol[index + 1..],
+                    None if symbol.chars().all(|c| c.is_ascii_digit()) => "",
+                    None => &symbol,
+                };
+                if !san.is_empty() {
+                    reader.play(number, san)?;
+                }
+            }
+        }
+    }
+    if let Some(message) = syntax_error {
+        return Err(PgnError::Syntax {
+            game: games.len() + 1,
+            message,
+        });
+    }
+    if !reader.is_empty() {
+        games.push(reader.finish(games.len() + 1)?);
+    }
+    Ok(games)
+}
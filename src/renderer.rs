@@ -0,0 +1,510 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+
+use sdl2::image::LoadTexture;
+use sdl2::pixels::Color;
+use sdl2::rect::Rect;
+use sdl2::render::{BlendMode, Texture, TextureCreator, WindowCanvas};
+use sdl2::video::WindowContext;
+
+use crate::font;
+use crate::theme::Theme;
+use chess_engine::chess::{Board, Move, PColor, Piece, PieceType, PROMOTION_PIECES};
+use chess_engine::clock::{format_clock, Clock};
+use chess_engine::game::Outcome;
+use chess_engine::movelist::MoveList;
+
+pub const WINDOW_SIZE: u32 = 1000;
+pub const BOARD_SIZE: u32 = 8;
+pub const SQUARE_SIZE: u32 = WINDOW_SIZE / BOARD_SIZE;
+const PIECE_SIZE: u32 = SQUARE_SIZE * 9 / 10;
+// The move list sits right of the board
+pub const PANEL_WIDTH: u32 = 300;
+const ROW_HEIGHT: u32 = 24;
+const TEXT_SCALE: u32 = 2;
+// Left edges of the move number, White's move and Black's move
+const NUMBER_X: i32 = WINDOW_SIZE as i32 + 12;
+const WHITE_MOVE_X: i32 = WINDOW_SIZE as i32 + 76;
+const BLACK_MOVE_X: i32 = WINDOW_SIZE as i32 + 186;
+const MOVE_CELL_WIDTH: u32 = 104;
+// Room left of a move inside its highlighted cell
+const CELL_PADDING: i32 = 6;
+// The clocks take the top and bottom of the panel, around the move list
+const CLOCK_HEIGHT: u32 = 64;
+const CLOCK_SCALE: u32 = 5;
+// Coordinate labels sit this far inside the corner of their square
+const LABEL_MARGIN: i32 = 5;
+
+const PIECE_TYPES: [PieceType; 6] = [
+    PieceType::Pawn,
+    PieceType::Rook,
+    PieceType::Knight,
+    PieceType::Bishop,
+    PieceType::Queen,
+    PieceType::King,
+];
+
+/// A piece texture that could not be loaded.
+#[derive(Debug)]
+pub struct TextureError {
+    pub path: PathBuf,
+    pub message: String,
+}
+
+impl fmt::Display for TextureError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "could not load piece texture '{}': {}",
+            self.path.display(),
+            self.message
+        )
+    }
+}
+
+impl std::error::Error for TextureError {}
+
+/// The twelve piece images, loaded once.
+pub struct PieceTextures<'a> {
+    textures: HashMap<(PieceType, PColor), Texture<'a>>,
+}
+
+impl<'a> PieceTextures<'a> {
+    /// Loads `<dir>/<piece>-<color>.png` for every piece, e.g.
+    /// `textures/knight-white.png`.
+    pub fn load(
+        creator: &'a TextureCreator<WindowContext>,
+        dir: &Path,
+    ) -> Result<PieceTextures<'a>, TextureError> {
+        let mut textures = HashMap::new();
+        for p_type in PIECE_TYPES {
+            for p_color in [PColor::White, PColor::Black] {
+                let name = format!("{:?}-{:?}.png", p_type, p_color).to_lowercase();
+                let path = dir.join(name);
+                if !path.is_file() {
+                    return Err(TextureError {
+                        path,
+                        message: "file not found".to_string(),
+                    });
+                }
+                let texture = creator
+                    .load_texture(&path)
+                    .map_err(|message| TextureError { path, message })?;
+                textures.insert((p_type, p_color), texture);
+            }
+        }
+        Ok(PieceTextures { textures })
+    }
+
+    fn get(&self, piece: Piece) -> &Texture<'a> {
+        // Every piece was loaded or `load` failed
+        &self.textures[&(piece.p_type, piece.p_color)]
+    }
+}
+
+/// Everything shown in one frame.
+pub struct Frame<'b> {
+    pub board: &'b Board,
+    pub selected: Option<(usize, usize)>,
+    /// Legal moves of the selected piece.
+    pub targets: &'b [Move],
+    /// A promotion waiting for the player to pick a piece.
+    pub promotion: Option<Move>,
+    /// The square of a piece being dragged, and the cursor position.
+    pub dragging: Option<((usize, usize), (i32, i32))>,
+    pub move_list: &'b MoveList,
+    pub outcome: Option<Outcome>,
+    /// The player offering a draw.
+    pub draw_offer: Option<PColor>,
+    /// Black at the bottom of the board instead of White.
+    pub flipped: bool,
+    /// Rank and file labels along the edges.
+    pub coordinates: bool,
+    pub clock: Option<&'b Clock>,
+}
+
+/// Draws the board, the pieces and the move list into the window.
+pub struct Renderer<'a> {
+    canvas: WindowCanvas,
+    textures: PieceTextures<'a>,
+    theme: Theme,
+}
+
+impl<'a> Renderer<'a> {
+    pub fn new(
+        mut canvas: WindowCanvas,
+        textures: PieceTextures<'a>,
+        theme: Theme,
+    ) -> Renderer<'a> {
+        // Highlights are see-through
+        canvas.set_blend_mode(BlendMode::Blend);
+        Renderer {
+            canvas,
+            textures,
+            theme,
+        }
+    }
+
+    pub fn draw(&mut self, frame: &Frame) {
+        self.draw_board(frame);
+        self.draw_move_list(frame.move_list, frame.clock.is_some());
+        if let Some(clock) = frame.clock {
+            self.draw_clocks(clock, frame.flipped);
+        }
+        if let Some(promotion) = frame.promotion {
+            self.draw_promotion_picker(promotion, frame.board.turn(), frame.flipped);
+        }
+        // The dragged piece is drawn last, above everything, centred on the cursor
+        if let Some((from, (x, y))) = frame.dragging {
+            if let Some(piece) = frame.board.piece_at(from) {
+                let half = SQUARE_SIZE as i32 / 2;
+                self.draw_piece(piece, x - half, y - half);
+            }
+        }
+
+        // Grey out the finished game and show the result in the title bar
+        let title = if let Some(outcome) = frame.outcome {
+            self.canvas.set_draw_color(self.theme.game_over);
+            self.canvas
+                .fill_rect(Rect::new(0, 0, WINDOW_SIZE, WINDOW_SIZE))
+                .unwrap();
+            format!("Chess - {}", outcome)
+        } else if let Some(color) = frame.draw_offer {
+            format!("Chess - {:?} offers a draw", color)
+        } else {
+            "Chess".to_string()
+        };
+        if self.canvas.window().title() != title {
+            self.canvas.window_mut().set_title(&title).unwrap();
+        }
+
+        self.canvas.present();
+    }
+
+    fn draw_board(&mut self, frame: &Frame) {
+        let board = frame.board;
+        let last_move = board.history().last().map(|entry| entry.mv);
+        let king_in_check = if board.is_in_check(board.turn()) {
+            board.find(Piece {
+                p_type: PieceType::King,
+                p_color: board.turn(),
+            })
+        } else {
+            None
+        };
+
+        for rank in 0..BOARD_SIZE as usize {
+            for file in 0..BOARD_SIZE as usize {
+                let square = (rank, file);
+                let (x, y) = square_origin(square, frame.flipped);
+                let dark = (rank + file) % 2 == 0;
+
+                self.fill_square(
+                    square,
+                    frame.flipped,
+                    if dark {
+                        self.theme.dark_square
+                    } else {
+                        self.theme.light_square
+                    },
+                );
+                if last_move.is_some_and(|mv| mv.from == square || mv.to == square) {
+                    self.fill_square(square, frame.flipped, self.theme.last_move);
+                }
+                if frame.selected == Some(square) {
+                    self.fill_square(square, frame.flipped, self.theme.selected);
+                }
+                if king_in_check == Some(square) {
+                    self.draw_check_glow(square, frame.flipped);
+                }
+
+                if frame.coordinates {
+                    self.draw_coordinates(square, frame.flipped, dark);
+                }
+
+                // A dragged piece is not shown on its square
+                if let Some(piece) = board.piece_at(square) {
+                    if frame.dragging.map(|(from, _)| from) != Some(square) {
+                        self.draw_piece(piece, x, y);
+                    }
+                }
+
+                // Where the selected piece can go: a dot on an empty square,
+                // a ring around a piece it can take
+                if let Some(mv) = frame.targets.iter().find(|mv| mv.to == square) {
+                    let half = SQUARE_SIZE as i32 / 2;
+                    let center = (x + half, y + half);
+                    let (outer, inner) = if mv.captured.is_some() {
+                        (half, half * 4 / 5)
+                    } else {
+                        (half / 3, 0)
+                    };
+                    self.fill_ring(center, outer, inner, self.theme.target);
+                }
+            }
+        }
+    }
+
+    fn fill_square(&mut self, square: (usize, usize), flipped: bool, color: Color) {
+        let (x, y) = square_origin(square, flipped);
+        self.canvas.set_draw_color(color);
+        self.canvas
+            .fill_rect(Rect::new(x, y, SQUARE_SIZE, SQUARE_SIZE))
+            .unwrap();
+    }
+
+    // Discs of the check colour stacked smaller and smaller, so the red
+    // gets stronger towards the king
+    fn draw_check_glow(&mut self, square: (usize, usize), flipped: bool) {
+        let (x, y) = square_origin(square, flipped);
+        let half = SQUARE_SIZE as i32 / 2;
+        for step in 0..4 {
+            let radius = half - step * half / 5;
+            self.fill_ring((x + half, y + half), radius, 0, self.theme.check);
+        }
+    }
+
+    // Fills the ring between the `inner` and `outer` radius around
+    // `center`, a disc if `inner` is 0
+    fn fill_ring(&mut self, center: (i32, i32), outer: i32, inner: i32, color: Color) {
+        let (cx, cy) = center;
+        // Half the width of a circle of `radius` at `dy` from its middle
+        let half_width = |radius: i32, dy: i32| f64::from(radius * radius - dy * dy).sqrt() as i32;
+        self.canvas.set_draw_color(color);
+        for dy in -outer..outer {
+            let outer_half = half_width(outer, dy);
+            let rows = if dy.abs() < inner {
+                let inner_half = half_width(inner, dy);
+                let width = (outer_half - inner_half) as u32;
+                vec![
+                    Rect::new(cx - outer_half, cy + dy, width, 1),
+                    Rect::new(cx + inner_half, cy + dy, width, 1),
+                ]
+            } else {
+                vec![Rect::new(
+                    cx - outer_half,
+                    cy + dy,
+                    2 * outer_half as u32,
+                    1,
+                )]
+            };
+            self.canvas.fill_rects(&rows).unwrap();
+        }
+    }
+
+    // The file letter along the bottom edge and the rank number along the
+    // left edge, in the colour of the other kind of square
+    fn draw_coordinates(&mut self, square: (usize, usize), flipped: bool, dark: bool) {
+        let (x, y) = square_origin(square, flipped);
+        let color = if dark {
+            self.theme.light_square
+        } else {
+            self.theme.dark_square
+        };
+        let (bottom_rank, left_file) = if flipped { (7, 7) } else { (0, 0) };
+        if square.0 == bottom_rank {
+            let label = ((b'a' + square.1 as u8) as char).to_string();
+            let width = font::text_width(&label, TEXT_SCALE) as i32;
+            let height = (font::GLYPH_HEIGHT * TEXT_SCALE) as i32;
+            font::draw_text(
+                &mut self.canvas,
+                &label,
+                x + SQUARE_SIZE as i32 - LABEL_MARGIN - width,
+                y + SQUARE_SIZE as i32 - LABEL_MARGIN - height,
+                TEXT_SCALE,
+                color,
+            );
+        }
+        if square.1 == left_file {
+            let label = (square.0 + 1).to_string();
+            font::draw_text(
+                &mut self.canvas,
+                &label,
+                x + LABEL_MARGIN,
+                y + LABEL_MARGIN,
+                TEXT_SCALE,
+                color,
+            );
+        }
+    }
+
+    fn draw_promotion_picker(&mut self, mv: Move, color: PColor, flipped: bool) {
+        for (index, promotion) in PROMOTION_PIECES.iter().enumerate() {
+            let rank = if mv.to.0 == 7 { 7 - index } else { index };
+            let (x, y) = square_origin((rank, mv.to.1), flipped);
+
+            self.canvas.set_draw_color(self.theme.promotion_picker);
+            self.canvas
+                .fill_rect(Rect::new(x, y, SQUARE_SIZE, SQUARE_SIZE))
+                .unwrap();
+            self.draw_piece(
+                Piece {
+                    p_type: *promotion,
+                    p_color: color,
+                },
+                x,
+                y,
+            );
+        }
+    }
+
+    // Draws `piece` in the square whose top left corner is `(x, y)`
+    fn draw_piece(&mut self, piece: Piece, x: i32, y: i32) {
+        let margin = ((SQUARE_SIZE - PIECE_SIZE) / 2) as i32;
+        let piece_rect = Rect::new(x + margin, y + margin, PIECE_SIZE, PIECE_SIZE);
+        self.canvas
+            .copy(self.textures.get(piece), None, piece_rect)
+            .expect("Failed to copy image.");
+    }
+
+    // Each side's clock on its own side of the board, the running one lit
+    fn draw_clocks(&mut self, clock: &Clock, flipped: bool) {
+        let now = Instant::now();
+        let (top, bottom) = if flipped {
+            (PColor::White, PColor::Black)
+        } else {
+            (PColor::Black, PColor::White)
+        };
+        for (color, y) in [(top, 0), (bottom, (WINDOW_SIZE - CLOCK_HEIGHT) as i32)] {
+            if clock.running() == Some(color) {
+                self.canvas.set_draw_color(self.theme.current_move);
+                self.canvas
+                    .fill_rect(Rect::new(WINDOW_SIZE as i32, y, PANEL_WIDTH, CLOCK_HEIGHT))
+                    .unwrap();
+            }
+            let text = format_clock(clock.remaining(color, now));
+            let width = font::text_width(&text, CLOCK_SCALE);
+            font::draw_text(
+                &mut self.canvas,
+                &text,
+                (WINDOW_SIZE + (PANEL_WIDTH - width) / 2) as i32,
+                y + ((CLOCK_HEIGHT - font::GLYPH_HEIGHT * CLOCK_SCALE) / 2) as i32,
+                CLOCK_SCALE,
+                self.theme.move_text,
+            );
+        }
+    }
+
+    fn draw_move_list(&mut self, move_list: &MoveList, has_clock: bool) {
+        self.canvas.set_draw_color(self.theme.panel);
+        self.canvas
+            .fill_rect(Rect::new(WINDOW_SIZE as i32, 0, PANEL_WIDTH, WINDOW_SIZE))
+            .unwrap();
+
+        let text_offset = ((ROW_HEIGHT - font::GLYPH_HEIGHT * TEXT_SCALE) / 2) as i32;
+        let current = move_list.ply().checked_sub(1);
+        let (top, fit) = move_list_area(has_clock);
+        for (row, (number, white, black)) in visible_rows(move_list, fit).into_iter().enumerate() {
+            let y = top + (row as u32 * ROW_HEIGHT) as i32;
+            font::draw_text(
+                &mut self.canvas,
+                &format!("{}.", number),
+                NUMBER_X,
+                y + text_offset,
+                TEXT_SCALE,
+                self.theme.move_number,
+            );
+            for (index, x) in [(white, WHITE_MOVE_X), (black, BLACK_MOVE_X)] {
+                let Some(index) = index else { continue };
+                if Some(index) == current {
+                    self.canvas.set_draw_color(self.theme.current_move);
+                    self.canvas
+                        .fill_rect(Rect::new(x - CELL_PADDING, y, MOVE_CELL_WIDTH, ROW_HEIGHT))
+                        .unwrap();
+                }
+                font::draw_text(
+                    &mut self.canvas,
+                    &move_list.sans()[index],
+                    x,
+                    y + text_offset,
+                    TEXT_SCALE,
+                    self.theme.move_text,
+                );
+            }
+        }
+    }
+}
+
+// Window position of the top left corner of `square`
+fn square_origin(square: (usize, usize), flipped: bool) -> (i32, i32) {
+    let (row, column) = if flipped {
+        (square.0, 7 - square.1)
+    } else {
+        (7 - square.0, square.1)
+    };
+    (
+        (column as u32 * SQUARE_SIZE) as i32,
+        (row as u32 * SQUARE_SIZE) as i32,
+    )
+}
+
+/// The `(rank, file)` of the board square at window position `(x, y)`,
+/// with Black at the bottom if `flipped`.
+pub fn square_at(x: i32, y: i32, flipped: bool) -> Option<(usize, usize)> {
+    let size = WINDOW_SIZE as i32;
+    if !(0..size).contains(&x) || !(0..size).contains(&y) {
+        return None;
+    }
+    let row = (y as u32 / SQUARE_SIZE) as usize;
+    let column = (x as u32 / SQUARE_SIZE) as usize;
+    if flipped {
+        Some((row, 7 - column))
+    } else {
+        Some((7 - row, column))
+    }
+}
+
+/// Which of `PROMOTION_PIECES` a click on `square` picks. The picker
+/// covers four squares of the promotion file, starting on the promotion
+/// square and running towards the middle of the board.
+pub fn promotion_index(mv: Move, square: (usize, usize)) -> Option<usize> {
+    if square.1 != mv.to.1 {
+        return None;
+    }
+    let index = (square.0 as i32 - mv.to.0 as i32).unsigned_abs() as usize;
+    (index < PROMOTION_PIECES.len()).then_some(index)
+}
+
+// Top of the move list and how many rows fit, leaving room for the clocks
+fn move_list_area(has_clock: bool) -> (i32, usize) {
+    let clocks = if has_clock { CLOCK_HEIGHT } else { 0 };
+    let fit = ((WINDOW_SIZE - 2 * clocks) / ROW_HEIGHT) as usize - 1;
+    (clocks as i32, fit)
+}
+
+// The last `fit` rows up to the current move, so it stays in view
+fn visible_rows(move_list: &MoveList, fit: usize) -> Vec<(u32, Option<usize>, Option<usize>)> {
+    let rows = move_list.rows();
+    let current = rows
+        .iter()
+        .position(|&(_, white, black)| {
+            let current = move_list.ply().checked_sub(1);
+            current.is_some() && (white == current || black == current)
+        })
+        .unwrap_or(0);
+    let first = (current + 1).saturating_sub(fit);
+    rows.into_iter().skip(first).take(fit).collect()
+}
+
+/// Index of the move drawn at window position `(x, y)` in the move list,
+/// which is shorter when the clocks are shown.
+pub fn move_list_index_at(move_list: &MoveList, has_clock: bool, x: i32, y: i32) -> Option<usize> {
+    let (top, fit) = move_list_area(has_clock);
+    if y < top {
+        return None;
+    }
+    let row = ((y - top) / ROW_HEIGHT as i32) as usize;
+    let (_, white, black) = *visible_rows(move_list, fit).get(row)?;
+    let in_cell = |left: i32| {
+        (left - CELL_PADDING..left - CELL_PADDING + MOVE_CELL_WIDTH as i32).contains(&x)
+    };
+    if in_cell(WHITE_MOVE_X) {
+        white
+    } else if in_cell(BLACK_MOVE_X) {
+        black
+    } else {
+        None
+    }
+}
@@ -0,0 +1,225 @@
+use std::fmt;
+
+use crate::chess::{parse_square, square_name, Board, Move, MoveFlag, PColor, Piece, PieceType};
+use crate::fen::piece_to_char;
+
+/// Why a SAN string could not be turned into a move.
+#[derive(Clone, Debug, PartialEq)]
+pub enum SanError {
+    /// The string is not written in SAN at all.
+    InvalidSyntax(String),
+    /// Well formed, but no legal move matches it.
+    IllegalMove(String),
+    /// Several legal moves match; the candidates in coordinate notation.
+    Ambiguous(String, Vec<String>),
+}
+
+impl fmt::Display for SanError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SanError::InvalidSyntax(san) => write!(f, "'{}' is not a move in SAN", san),
+            SanError::IllegalMove(san) => write!(f, "'{}' is not a legal move here", san),
+            SanError::Ambiguous(san, candidates) => write!(
+                f,
+                "'{}' is ambiguous, it could be any of {}",
+                san,
+                candidates.join(", ")
+            ),
+        }
+    }
+}
+
+impl std::error::Error for SanError {}
+
+fn piece_letter(p_type: PieceType) -> char {
+    piece_to_char(Piece {
+        p_type,
+        p_color: PColor::White,
+    })
+}
+
+fn piece_from_letter(letter: char) -> Option<PieceType> {
+    match letter {
+        'N' => Some(PieceType::Knight),
+        'B' => Some(PieceType::Bishop),
+        'R' => Some(PieceType::Rook),
+        'Q' => Some(PieceType::Queen),
+        'K' => Some(PieceType::King),
+        _ => None,
+    }
+}
+
+// What a SAN string says about the move it stands for
+struct SanPattern {
+    piece: PieceType,
+    from_file: Option<usize>,
+    from_rank: Option<usize>,
+    capture: bool,
+    to: (usize, usize),
+    promotion: Option<PieceType>,
+}
+
+impl SanPattern {
+    fn parse(san: &str) -> Option<SanPattern> {
+        let mut chars: Vec<char> = san.chars().collect();
+
+        let mut promotion = None;
+        if let Some(&last) = chars.last() {
+            if let Some(p_type) = piece_from_letter(last.to_ascii_uppercase()) {
+                if p_type == PieceType::King {
+                    return None;
+                }
+                promotion = Some(p_type);
+                chars.pop();
+                if chars.last() == Some(&'=') {
+                    chars.pop();
+                }
+            }
+        }
+
+        if chars.len() < 2 {
+            return None;
+        }
+        let to_name: String = chars.split_off(chars.len() - 2).into_iter().collect();
+        let to = parse_square(&to_name)?;
+
+        let mut rest = chars.as_slice();
+        let piece = match rest.first().and_then(|&c| piece_from_letter(c)) {
+            Some(p_type) => {
+                rest = &rest[1..];
+                p_type
+            }
+            None => PieceType::Pawn,
+        };
+        let capture = rest.last() == Some(&'x');
+        if capture {
+            rest = &rest[..rest.len() - 1];
+        }
+
+        // Whatever is left tells the from square apart: a file, a rank or both
+        let mut from_file = None;
+        let mut from_rank = None;
+        for &c in rest {
+            match c {
+                'a'..='h' if from_file.is_none() && from_rank.is_none() => {
+                    from_file = Some(c as usize - 'a' as usize)
+                }
+                '1'..='8' if from_rank.is_none() => from_rank = Some(c as usize - '1' as usize),
+                _ => return None,
+            }
+        }
+
+        if promotion.is_some() && piece != PieceType::Pawn {
+            return None;
+        }
+        Some(SanPattern {
+            piece,
+            from_file,
+            from_rank,
+            capture,
+            to,
+            promotion,
+        })
+    }
+
+    fn matches(&self, mv: &Move) -> bool {
+        mv.piece == self.piece
+            && mv.flag != MoveFlag::Castling
+            && mv.to == self.to
+            && mv.promotion == self.promotion
+            && (!self.capture || mv.captured.is_some())
+            && self.from_file.is_none_or(|file| mv.from.1 == file)
+            && self.from_rank.is_none_or(|rank| mv.from.0 == rank)
+    }
+}
+
+impl Board {
+    /// `mv` in standard algebraic notation, e.g. "Nbxd7+", "exd6", "e8=Q#"
+    /// or "O-O-O". `mv` must be legal in the current position; the board
+    /// is left as it was.
+    pub fn move_to_san(&mut self, mv: &Move) -> String {
+        let mut san = String::new();
+        if mv.flag == MoveFlag::Castling {
+            san.push_str(if mv.to.1 == 6 { "O-O" } else { "O-O-O" });
+        } else if mv.piece == PieceType::Pawn {
+            if mv.captured.is_some() {
+                san.push(square_name(mv.from).chars().next().unwrap());
+                san.push('x');
+            }
+            san.push_str(&square_name(mv.to));
+            if let Some(promotion) = mv.promotion {
+                san.push('=');
+                san.push(piece_letter(promotion));
+            }
+        } else {
+            san.push(piece_letter(mv.piece));
+
+            // Name the file if that tells the moves apart, else the rank,
+            // and the whole square only if neither does on its own
+            let others: Vec<(usize, usize)> = self
+                .legal_moves()
+                .iter()
+                .filter(|other| other.piece == mv.piece && other.to == mv.to)
+                .map(|other| other.from)
+                .filter(|&from| from != mv.from)
+                .collect();
+            if !others.is_empty() {
+                let from = square_name(mv.from);
+                if others.iter().all(|other| other.1 != mv.from.1) {
+                    san.push_str(&from[..1]);
+                } else if others.iter().all(|other| other.0 != mv.from.0) {
+                    san.push_str(&from[1..]);
+                } else {
+                    san.push_str(&from);
+                }
+            }
+
+            if mv.captured.is_some() {
+                san.push('x');
+            }
+            san.push_str(&square_name(mv.to));
+        }
+
+        self.make_move(*mv);
+        if self.is_in_check(self.turn) {
+            san.push(if self.legal_moves().is_empty() {
+                '#'
+            } else {
+                '+'
+            });
+        }
+        self.unmake_move();
+        san
+    }
+
+    /// The legal move written as `san`. Check and annotation marks are
+    /// optional, and "0-0" is accepted for "O-O".
+    pub fn parse_san(&mut self, san: &str) -> Result<Move, SanError> {
+        let trimmed = san.trim().trim_end_matches(['+', '#', '!', '?']);
+        let legal = self.legal_moves();
+
+        let candidates: Vec<Move> = match trimmed {
+            "O-O" | "0-0" | "O-O-O" | "0-0-0" => {
+                let file = if trimmed.len() == 3 { 6 } else { 2 };
+                legal
+                    .into_iter()
+                    .filter(|mv| mv.flag == MoveFlag::Castling && mv.to.1 == file)
+                    .collect()
+            }
+            _ => {
+                let pattern = SanPattern::parse(trimmed)
+                    .ok_or_else(|| SanError::InvalidSyntax(san.to_string()))?;
+                legal.into_iter().filter(|mv| pattern.matches(mv)).collect()
+            }
+        };
+
+        match candidates.as_slice() {
+            [mv] => Ok(*mv),
+            [] => Err(SanError::IllegalMove(san.to_string())),
+            _ => Err(SanError::Ambiguous(
+                san.to_string(),
+                candidates.iter().map(|mv| mv.to_string()).collect(),
+            )),
+        }
+    }
+}
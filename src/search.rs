@@ -0,0 +1,349 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use crate::chess::{Board, Move};
+use crate::eval::{evaluate, piece_value};
+use crate::tt::{Bound, TranspositionTable, TtEntry};
+
+/// Score of being mated right now; mates further away score closer to 0.
+pub const MATE_SCORE: i32 = 30_000;
+const INFINITY: i32 = 32_000;
+const MAX_DEPTH: u32 = 64;
+
+/// When the search has to stop. With neither a depth nor a time it runs
+/// until `stop` is set.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct SearchLimits {
+    pub depth: Option<u32>,
+    pub time: Option<Duration>,
+}
+
+impl SearchLimits {
+    pub fn depth(depth: u32) -> SearchLimits {
+        SearchLimits {
+            depth: Some(depth),
+            time: None,
+        }
+    }
+
+    pub fn time(time: Duration) -> SearchLimits {
+        SearchLimits {
+            depth: None,
+            time: Some(time),
+        }
+    }
+}
+
+/// Result of the deepest completed iteration.
+#[derive(Clone, Debug, Default)]
+pub struct SearchResult {
+    pub best_move: Option<Move>,
+    /// Centipawns from the point of view of the side to move.
+    pub score: i32,
+    pub depth: u32,
+    pub nodes: u64,
+    /// Transposition table usage in permille.
+    pub hashfull: u32,
+    /// Principal variation, starting with `best_move`.
+    pub pv: Vec<Move>,
+}
+
+impl SearchResult {
+    /// Moves until mate if the score is a mate score, negative when the
+    /// side to move is getting mated.
+    pub fn mate_in(&self) -> Option<i32> {
+        if self.score.abs() < MATE_SCORE - MAX_DEPTH as i32 {
+            return None;
+        }
+        let plies = MATE_SCORE - self.score.abs();
+        let moves = (plies + 1) / 2;
+        Some(if self.score > 0 { moves } else { -moves })
+    }
+}
+
+/// Iterative deepening negamax alpha-beta search with a quiescence search
+/// on captures.
+pub struct Searcher {
+    pub limits: SearchLimits,
+    /// Set from another thread to abort the search early.
+    pub stop: Arc<AtomicBool>,
+    /// Kept between searches, so it can be reused for the next move.
+    pub tt: TranspositionTable,
+    nodes: u64,
+    deadline: Option<Instant>,
+    aborted: bool,
+}
+
+impl Searcher {
+    pub fn new(limits: SearchLimits) -> Searcher {
+        Searcher {
+            limits,
+            stop: Arc::new(AtomicBool::new(false)),
+            tt: TranspositionTable::default(),
+            nodes: 0,
+            deadline: None,
+            aborted: false,
+        }
+    }
+
+    /// Searches `board` within the limits. The board is left as it was.
+    pub fn search(&mut self, board: &mut Board) -> SearchResult {
+        self.search_with(board, |_| {})
+    }
+
+    /// Like `search`, calling `report` after every completed iteration so
+    /// progress can be shown while the search runs.
+    pub fn search_with(
+        &mut self,
+        board: &mut Board,
+        mut report: impl FnMut(&SearchResult),
+    ) -> SearchResult {
+        self.nodes = 0;
+        self.aborted = false;
+        self.deadline = self.limits.time.map(|time| Instant::now() + time);
+        let max_depth = self.limits.depth.unwrap_or(MAX_DEPTH).clamp(1, MAX_DEPTH);
+
+        let mut result = SearchResult::default();
+        for depth in 1..=max_depth {
+            let mut pv = Vec::new();
+            let score = self.negamax(board, depth, 0, -INFINITY, INFINITY, &result.pv, &mut pv);
+            // An unfinished iteration is not trusted, except that the first
+            // one must give some move to play
+            if self.aborted && result.best_move.is_some() {
+                break;
+            }
+            result = SearchResult {
+                best_move: pv.first().copied(),
+                score,
+                depth,
+                nodes: self.nodes,
+                hashfull: self.tt.hashfull(),
+                pv,
+            };
+            report(&result);
+            if self.aborted || result.mate_in().is_some() {
+                break;
+            }
+        }
+        // Stopped before even one move was searched: any legal move will do
+        if result.best_move.is_none() {
+            result.best_move = board.legal_moves().first().copied();
+            result.pv = result.best_move.into_iter().collect();
+        }
+        result.nodes = self.nodes;
+        result
+    }
+
+    fn should_stop(&mut self) -> bool {
+        // Checking the clock on every node would be wasteful
+        if self.nodes & 2047 == 0 {
+            if self.stop.load(Ordering::Relaxed) {
+                self.aborted = true;
+            }
+            if let Some(deadline) = self.deadline {
+                if Instant::now() >= deadline {
+                    self.aborted = true;
+                }
+            }
+        }
+        self.aborted
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn negamax(
+        &mut self,
+        board: &mut Board,
+        depth: u32,
+        ply: u32,
+        mut alpha: i32,
+        beta: i32,
+        previous_pv: &[Move],
+        pv: &mut Vec<Move>,
+    ) -> i32 {
+        self.nodes += 1;
+        if self.should_stop() {
+            return 0;
+        }
+
+        if ply > 0
+            && (board.halfmove_clock >= 100
+                || board.repetitions() >= 2
+                || board.insufficient_material())
+        {
+            return 0;
+        }
+
+        // A result from an earlier search can settle this node, except at the
+        // root where a move has to come out of it
+        let tt_entry = self.tt.probe(board.hash());
+        if let Some(entry) = tt_entry {
+            let score = score_from_tt(entry.score, ply);
+            let usable = match entry.bound {
+                Bound::Exact => true,
+                Bound::Lower => score >= beta,
+                Bound::Upper => score <= alpha,
+            };
+            if ply > 0 && entry.depth >= depth && usable {
+                pv.extend(entry.best_move);
+                return score;
+            }
+        }
+
+        let mut moves = board.legal_moves();
+        if moves.is_empty() {
+            return if board.is_in_check(board.turn) {
+                -MATE_SCORE + ply as i32
+            } else {
+                0
+            };
+        }
+        if depth == 0 {
+            return self.quiescence(board, alpha, beta);
+        }
+
+        let hash_move = previous_pv
+            .first()
+            .copied()
+            .or(tt_entry.and_then(|entry| entry.best_move));
+        order_moves(&mut moves, hash_move);
+
+        let original_alpha = alpha;
+        let mut best_score = -INFINITY;
+        let mut best_move = None;
+        for mv in moves {
+            // Follow the previous principal variation first
+            let child_pv = match previous_pv.first() {
+                Some(&pv_move) if pv_move == mv => &previous_pv[1..],
+                _ => &[],
+            };
+            let mut line = Vec::new();
+            board.make_move(mv);
+            let score = -self.negamax(
+                board,
+                depth - 1,
+                ply + 1,
+                -beta,
+                -alpha,
+                child_pv,
+                &mut line,
+            );
+            board.unmake_move();
+            if self.aborted {
+                return 0;
+            }
+
+            if score > best_score {
+                best_score = score;
+                best_move = Some(mv);
+                if score > alpha {
+                    alpha = score;
+                    pv.clear();
+                    pv.push(mv);
+                    pv.extend(line);
+                }
+            }
+            if alpha >= beta {
+                break;
+            }
+        }
+
+        let bound = if best_score >= beta {
+            Bound::Lower
+        } else if best_score > original_alpha {
+            Bound::Exact
+        } else {
+            Bound::Upper
+        };
+        self.tt.store(TtEntry {
+            key: board.hash(),
+            depth,
+            score: score_to_tt(best_score, ply),
+            bound,
+            best_move,
+        });
+        best_score
+    }
+
+    // Only captures and promotions are searched, so the evaluation is not
+    // taken in the middle of an exchange
+    fn quiescence(&mut self, board: &mut Board, mut alpha: i32, beta: i32) -> i32 {
+        self.nodes += 1;
+        if self.should_stop() {
+            return 0;
+        }
+
+        let stand_pat = evaluate(board);
+        if stand_pat >= beta {
+            return stand_pat;
+        }
+        alpha = alpha.max(stand_pat);
+
+        let mut moves: Vec<Move> = board
+            .legal_moves()
+            .into_iter()
+            .filter(|mv| mv.captured.is_some() || mv.promotion.is_some())
+            .collect();
+        order_moves(&mut moves, None);
+
+        for mv in moves {
+            board.make_move(mv);
+            let score = -self.quiescence(board, -beta, -alpha);
+            board.unmake_move();
+            if self.aborted {
+                return 0;
+            }
+            if score >= beta {
+                return score;
+            }
+            alpha = alpha.max(score);
+        }
+        alpha
+    }
+}
+
+// Mate scores count plies from the root, but a stored entry may be found
+// at another ply: store them counted from the node instead
+fn score_to_tt(score: i32, ply: u32) -> i32 {
+    if score >= MATE_SCORE - MAX_DEPTH as i32 {
+        score + ply as i32
+    } else if score <= -MATE_SCORE + MAX_DEPTH as i32 {
+        score - ply as i32
+    } else {
+        score
+    }
+}
+
+fn score_from_tt(score: i32, ply: u32) -> i32 {
+    if score >= MATE_SCORE - MAX_DEPTH as i32 {
+        score - ply as i32
+    } else if score <= -MATE_SCORE + MAX_DEPTH as i32 {
+        score + ply as i32
+    } else {
+        score
+    }
+}
+
+// Principal variation or hash move first, then captures with the most
+// valuable victim and least valuable attacker, then promotions, then quiet
+// moves
+fn order_moves(moves: &mut [Move], first: Option<Move>) {
+    moves.sort_by_cached_key(|mv| {
+        if Some(*mv) == first {
+            return i32::MIN;
+        }
+        let mut key = 0;
+        if let Some(captured) = mv.captured {
+            key -= 10 * piece_value(captured) - piece_value(mv.piece);
+        }
+        if let Some(promotion) = mv.promotion {
+            key -= piece_value(promotion);
+        }
+        key
+    });
+}
+
+/// Searches `board` for the best move to play.
+pub fn best_move(board: &mut Board, limits: SearchLimits) -> SearchResult {
+    Searcher::new(limits).search(board)
+}
@@ -0,0 +1,791 @@
+use crate::chess::{Board, DrawReason, GameStatus, IllegalMove, Move, MoveFlag, PColor, PieceType};
+use crate::clock::{format_clock, Clock, TimeControl};
+use crate::eval::{evaluate, evaluate_terms, pawn_structure};
+use crate::fen::{FenError, START_FEN};
+use crate::game::{Game, GameError, Termination};
+use crate::movelist::MoveList;
+use crate::pgn::{date_tag, parse_pgn, GameResult, PgnError, PgnGame};
+use crate::san::SanError;
+use crate::search::{best_move, SearchLimits};
+use crate::tt::{Bound, TranspositionTable, TtEntry};
+use crate::uci::{parse_position, set_position, GoParams};
+use std::time::{Duration, Instant, UNIX_EPOCH};
+
+// Published perft results, see https://www.chessprogramming.org/Perft_Results
+const KIWIPETE: &str = "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1";
+const POSITION_3: &str = "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1";
+const POSITION_4: &str = "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1";
+const POSITION_4_MIRRORED: &str =
+    "r2q1rk1/pP1p2pp/Q4n2/bbp1p3/Np6/1B3NBn/pPPP1PPP/R3K2R b KQ - 0 1";
+const POSITION_5: &str = "rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8";
+const POSITION_6: &str = "r4rk1/1pp1qppp/p1np1n2/2b1p1B1/2B1P1b1/P1NP1N2/1PP1QPPP/R4RK1 w - - 0 10";
+
+fn check_perft(fen: &str, expected: &[u64]) {
+    let mut board = Board::from_fen(fen).unwrap();
+    for (depth, &nodes) in expected.iter().enumerate() {
+        let depth = depth as u32 + 1;
+        assert_eq!(board.perft(depth), nodes, "perft({}) of {}", depth, fen);
+        // Make/unmake must leave the position exactly as it was
+        assert_eq!(board.to_fen(), Board::from_fen(fen).unwrap().to_fen());
+    }
+}
+
+#[test]
+fn perft_start_position() {
+    check_perft(START_FEN, &[20, 400, 8902, 197281]);
+}
+
+#[test]
+fn perft_kiwipete() {
+    check_perft(KIWIPETE, &[48, 2039, 97862]);
+}
+
+#[test]
+fn perft_position_3() {
+    check_perft(POSITION_3, &[14, 191, 2812, 43238]);
+}
+
+#[test]
+fn perft_position_4() {
+    check_perft(POSITION_4, &[6, 264, 9467]);
+    check_perft(POSITION_4_MIRRORED, &[6, 264, 9467]);
+}
+
+#[test]
+fn perft_position_5() {
+    check_perft(POSITION_5, &[44, 1486, 62379]);
+}
+
+#[test]
+fn perft_position_6() {
+    check_perft(POSITION_6, &[46, 2079, 89890]);
+}
+
+#[test]
+fn perft_divide_adds_up() {
+    let mut board = Board::new();
+    let divide = board.perft_divide(3);
+    assert_eq!(divide.len(), 20);
+    assert_eq!(divide.iter().map(|(_, nodes)| nodes).sum::<u64>(), 8902);
+}
+
+// The deeper counts take a while in debug builds:
+// cargo test --release -- --ignored
+#[test]
+#[ignore]
+fn perft_deep() {
+    check_perft(START_FEN, &[20, 400, 8902, 197281, 4865609]);
+    check_perft(KIWIPETE, &[48, 2039, 97862, 4085603]);
+    check_perft(POSITION_3, &[14, 191, 2812, 43238, 674624, 11030083]);
+    check_perft(POSITION_4, &[6, 264, 9467, 422333]);
+    check_perft(POSITION_5, &[44, 1486, 62379, 2103487]);
+    check_perft(POSITION_6, &[46, 2079, 89890, 3894594]);
+}
+
+#[test]
+fn fen_errors() {
+    let error = |fen: &str| Board::from_fen(fen).err().unwrap();
+    assert_eq!(error("8/8/8/8/8/8/8/8 w -"), FenError::WrongFieldCount(3));
+    assert_eq!(
+        error("4k3/8/8/8/8/8/4K3 w - -"),
+        FenError::WrongRankCount(7)
+    );
+    assert_eq!(
+        error("4k3/8/8/8/8/8/8/4K4 w - -"),
+        FenError::WrongRankLength { rank: 0, length: 9 }
+    );
+    assert_eq!(
+        error("4k3/8/8/8/8/8/8/4X3 w - -"),
+        FenError::InvalidPiece('X')
+    );
+    assert_eq!(
+        error("4k3/8/8/8/8/8/8/8 w - -"),
+        FenError::KingCount(PColor::White, 0)
+    );
+    assert_eq!(
+        error("P3k3/8/8/8/8/8/8/4K3 w - - 0 1"),
+        FenError::PawnOnBackRank((7, 0))
+    );
+    assert_eq!(
+        error("4k3/8/8/8/8/8/8/4K2p w - - 0 1"),
+        FenError::PawnOnBackRank((0, 7))
+    );
+    assert_eq!(
+        error("4k3/8/8/8/8/8/8/4K3 x - -"),
+        FenError::InvalidSideToMove("x".to_string())
+    );
+    assert_eq!(
+        error("4k3/8/8/8/8/8/8/4K3 w KK -"),
+        FenError::InvalidCastling("KK".to_string())
+    );
+    assert_eq!(
+        error("4k3/8/8/8/8/8/8/4K3 w - e4"),
+        FenError::InvalidEnPassant("e4".to_string())
+    );
+    // e3 only makes sense with Black to move, and no pawn stands on e5
+    assert_eq!(
+        error("4k3/8/8/8/8/8/3PP3/4K3 w - e3 0 1"),
+        FenError::ImpossibleEnPassant("e3".to_string())
+    );
+    assert_eq!(
+        error("4k3/8/8/8/8/8/8/4K3 w - e6 0 1"),
+        FenError::ImpossibleEnPassant("e6".to_string())
+    );
+    assert_eq!(
+        error("4k2R/8/8/8/8/8/8/4K3 w - - 0 1"),
+        FenError::OpponentInCheck(PColor::Black)
+    );
+    assert_eq!(
+        error("4k3/8/8/8/8/8/8/4K3 w - - x 1"),
+        FenError::InvalidHalfmoveClock("x".to_string())
+    );
+    assert_eq!(
+        error("4k3/8/8/8/8/8/8/4K3 w - - 0 0"),
+        FenError::InvalidFullmoveNumber("0".to_string())
+    );
+
+    // A real en passant square is fine
+    Board::from_fen("4k3/8/8/3pP3/8/8/8/4K3 w - d6 0 2").unwrap();
+}
+
+#[test]
+fn game_status_draws() {
+    let status = |fen: &str| Board::from_fen(fen).unwrap().game_status();
+    assert_eq!(
+        status("7k/5Q2/6K1/8/8/8/8/8 b - - 0 1"),
+        GameStatus::Stalemate
+    );
+    assert_eq!(
+        status("4k3/8/8/8/8/8/4R3/4K3 b - - 100 80"),
+        GameStatus::Draw(DrawReason::FiftyMoveRule)
+    );
+    assert_eq!(
+        status("4k3/8/8/8/8/8/4R3/4K3 b - - 99 80"),
+        GameStatus::Ongoing
+    );
+
+    let insufficient = GameStatus::Draw(DrawReason::InsufficientMaterial);
+    assert_eq!(status("4k3/8/8/8/8/8/8/4K3 w - - 0 1"), insufficient);
+    assert_eq!(status("4k3/8/8/8/8/8/8/4KN2 w - - 0 1"), insufficient);
+    assert_eq!(status("4k3/8/8/8/8/8/8/4KB2 w - - 0 1"), insufficient);
+    // Bishops that all stand on light squares, whoever owns them
+    assert_eq!(status("2b1k3/8/8/8/8/8/8/4KB2 w - - 0 1"), insufficient);
+    assert_eq!(
+        status("4kb2/8/8/8/8/8/8/4KB2 w - - 0 1"),
+        GameStatus::Ongoing
+    );
+
+    // The starting position for the third time
+    let mut board = Board::new();
+    for san in ["Nf3", "Nf6", "Ng1", "Ng8", "Nf3", "Nf6", "Ng1"] {
+        let mv = board.parse_san(san).unwrap();
+        board.apply_move(mv).unwrap();
+        assert_eq!(board.game_status(), GameStatus::Ongoing);
+    }
+    let mv = board.parse_san("Ng8").unwrap();
+    board.apply_move(mv).unwrap();
+    assert_eq!(
+        board.game_status(),
+        GameStatus::Draw(DrawReason::ThreefoldRepetition)
+    );
+}
+
+#[test]
+fn search_finds_mate_in_one() {
+    let mut board = Board::from_fen("6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1").unwrap();
+    let fen = board.to_fen();
+    let result = best_move(&mut board, SearchLimits::depth(4));
+    let mate = board.clone().parse_san("Ra8#").unwrap();
+    assert_eq!(result.best_move, Some(mate));
+    assert_eq!(result.mate_in(), Some(1));
+    // The search leaves the board as it found it
+    assert_eq!(board.to_fen(), fen);
+}
+
+#[test]
+fn search_limits() {
+    // Nothing to play when checkmated or stalemated
+    let mut mated = Board::from_fen("R5k1/5ppp/8/8/8/8/8/6K1 b - - 0 1").unwrap();
+    assert_eq!(
+        best_move(&mut mated, SearchLimits::depth(3)).best_move,
+        None
+    );
+    let mut stalemate = Board::from_fen("7k/5Q2/6K1/8/8/8/8/8 b - - 0 1").unwrap();
+    assert_eq!(
+        best_move(&mut stalemate, SearchLimits::depth(3)).best_move,
+        None
+    );
+
+    let mut board = Board::new();
+    let result = best_move(&mut board, SearchLimits::depth(3));
+    assert_eq!(result.depth, 3);
+    assert!(result.pv.len() <= 3);
+    assert!(board.is_legal(&result.best_move.unwrap()));
+}
+
+#[test]
+fn eval_is_symmetric() {
+    assert_eq!(evaluate_terms(&Board::new()).total(), 0);
+    let board = Board::from_fen(POSITION_4).unwrap();
+    let mirrored = Board::from_fen(POSITION_4_MIRRORED).unwrap();
+    assert_eq!(
+        evaluate_terms(&board).total(),
+        -evaluate_terms(&mirrored).total()
+    );
+    // Both sides to move see the same position from their own side
+    assert_eq!(evaluate(&board), evaluate(&mirrored));
+}
+
+#[test]
+fn eval_pawn_structure() {
+    // White has a passed pawn on d5, Black doubled and isolated pawns on the h-file
+    let board = Board::from_fen("4k3/7p/7p/3P4/8/8/8/4K3 w - - 0 1").unwrap();
+    let white = pawn_structure(&board, PColor::White);
+    let black = pawn_structure(&board, PColor::Black);
+    assert!(white.eg > 0);
+    assert!(black.mg < 0 && black.eg < 0);
+}
+
+#[test]
+fn uci_position() {
+    let board = parse_position(&["startpos", "moves", "e2e4", "e7e5", "g1f3"]).unwrap();
+    assert_eq!(
+        board.to_fen(),
+        "rnbqkbnr/pppp1ppp/8/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R b KQkq - 1 2"
+    );
+    let fen: Vec<&str> = KIWIPETE.split(' ').collect();
+    let board = parse_position(&[&["fen"], &fen[..]].concat()).unwrap();
+    assert_eq!(board.to_fen(), KIWIPETE);
+    assert!(parse_position(&["startpos", "moves", "e2e5"]).is_err());
+
+    // A bad command never leaves the previous position in place
+    let mut board = Board::from_fen("6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1").unwrap();
+    assert!(set_position(&mut board, &["startpos", "moves", "e2e4", "e7e6", "e2e5"]).is_err());
+    assert_eq!(
+        board.to_fen(),
+        "rnbqkbnr/pppp1ppp/4p3/8/4P3/8/PPPP1PPP/RNBQKBNR w KQkq - 0 2"
+    );
+    assert!(set_position(&mut board, &["fen", "8/8/8/8/8/8/8/8", "w", "-", "-"]).is_err());
+    assert_eq!(board.to_fen(), START_FEN);
+}
+
+#[test]
+fn uci_go_limits() {
+    let params = GoParams::parse(&["wtime", "60000", "btime", "1000", "winc", "2000"]);
+    let limits = params.limits(PColor::White);
+    assert_eq!(limits.time, Some(Duration::from_millis(2000 + 1500)));
+    assert_eq!(limits.depth, None);
+    // Never more than what is left on the clock
+    let limits = GoParams::parse(&["btime", "40", "movestogo", "1"]).limits(PColor::Black);
+    assert!(limits.time.unwrap() <= Duration::from_millis(40));
+
+    let limits = GoParams::parse(&["depth", "5", "movetime", "300"]).limits(PColor::White);
+    assert_eq!(limits.depth, Some(5));
+    assert_eq!(limits.time, Some(Duration::from_millis(300)));
+}
+
+// The incremental hash must always match one computed from scratch
+fn check_hash(board: &mut Board, depth: u32) {
+    let fresh = Board::from_fen(&board.to_fen()).unwrap();
+    assert_eq!(board.hash(), fresh.hash(), "{}", board.to_fen());
+    if depth == 0 {
+        return;
+    }
+    for mv in board.legal_moves() {
+        let before = board.hash();
+        board.make_move(mv);
+        check_hash(board, depth - 1);
+        board.unmake_move();
+        assert_eq!(board.hash(), before);
+    }
+}
+
+#[test]
+fn zobrist_incremental() {
+    check_hash(&mut Board::from_fen(KIWIPETE).unwrap(), 2);
+    check_hash(&mut Board::from_fen(POSITION_4).unwrap(), 2);
+    check_hash(&mut Board::from_fen(POSITION_3).unwrap(), 3);
+}
+
+#[test]
+fn zobrist_transpositions() {
+    let a = parse_position(&["startpos", "moves", "e2e4", "e7e5", "g1f3", "b8c6"]).unwrap();
+    let b = parse_position(&["startpos", "moves", "g1f3", "b8c6", "e2e4", "e7e5"]).unwrap();
+    assert_eq!(a.hash(), b.hash());
+    // Same pieces, other side to move
+    let c = parse_position(&["startpos", "moves", "g1f3", "g8f6", "f3g1"]).unwrap();
+    let d = parse_position(&["startpos", "moves", "g1f3", "g8f6", "f3g1", "f6g8"]).unwrap();
+    assert_ne!(c.hash(), d.hash());
+    assert_eq!(d.hash(), Board::new().hash());
+}
+
+#[test]
+fn tt_store_and_probe() {
+    let mut tt = TranspositionTable::new(1);
+    let entry = TtEntry {
+        key: Board::new().hash(),
+        depth: 4,
+        score: 25,
+        bound: Bound::Exact,
+        best_move: None,
+    };
+    tt.store(entry);
+    assert_eq!(tt.probe(entry.key), Some(entry));
+    // A shallower result does not replace a deeper one for the same position
+    tt.store(TtEntry { depth: 2, ..entry });
+    assert_eq!(tt.probe(entry.key), Some(entry));
+    assert_eq!(tt.probe(entry.key ^ 1), None);
+    tt.clear();
+    assert_eq!(tt.probe(entry.key), None);
+}
+
+// Every legal move must read back from its own SAN
+fn check_san_round_trip(fen: &str) {
+    let mut board = Board::from_fen(fen).unwrap();
+    for mv in board.legal_moves() {
+        let san = board.move_to_san(&mv);
+        assert_eq!(board.parse_san(&san), Ok(mv), "{} in {}", san, fen);
+    }
+}
+
+#[test]
+fn san_round_trip() {
+    for fen in [
+        START_FEN, KIWIPETE, POSITION_3, POSITION_4, POSITION_5, POSITION_6,
+    ] {
+        check_san_round_trip(fen);
+    }
+}
+
+#[test]
+fn san_formatting() {
+    // Knights on b1 and f3 can both reach d2, rooks on a1 and a5 both a3
+    let mut board = Board::from_fen("4k3/8/8/R7/8/5N2/4p3/RN2K3 w - - 0 1").unwrap();
+    let san = |board: &mut Board, uci: &str| {
+        let mv = board
+            .legal_moves()
+            .into_iter()
+            .find(|mv| mv.to_string() == uci)
+            .unwrap();
+        board.move_to_san(&mv)
+    };
+    assert_eq!(san(&mut board, "b1d2"), "Nbd2");
+    assert_eq!(san(&mut board, "a1a3"), "R1a3");
+    assert_eq!(san(&mut board, "a5a8"), "Ra8+");
+    assert_eq!(san(&mut board, "e1e2"), "Kxe2");
+
+    let mut board = Board::from_fen("r3k2r/1P6/8/8/8/8/8/R3K2R b KQkq - 0 1").unwrap();
+    assert_eq!(san(&mut board, "e8g8"), "O-O");
+    assert_eq!(
+        board.parse_san("O-O-O"),
+        Err(SanError::IllegalMove("O-O-O".to_string()))
+    );
+    let mut board = Board::from_fen("r3k2r/1P6/8/8/8/8/8/R3K2R w KQkq - 0 1").unwrap();
+    assert_eq!(san(&mut board, "b7a8q"), "bxa8=Q+");
+    assert_eq!(san(&mut board, "b7b8n"), "b8=N");
+
+    let mut board = Board::from_fen("6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1").unwrap();
+    assert_eq!(san(&mut board, "a1a8"), "Ra8#");
+}
+
+#[test]
+fn san_errors() {
+    let mut board = Board::from_fen("4k3/8/8/R7/8/5N2/4p3/RN2K3 w - - 0 1").unwrap();
+    assert!(matches!(
+        board.parse_san("Nd2"),
+        Err(SanError::Ambiguous(_, _))
+    ));
+    assert_eq!(board.parse_san("N1d2").unwrap().to_string(), "b1d2");
+    assert_eq!(
+        board.parse_san("Nfxd2").unwrap_err(),
+        SanError::IllegalMove("Nfxd2".to_string())
+    );
+    assert_eq!(
+        board.parse_san("Qd4"),
+        Err(SanError::IllegalMove("Qd4".to_string()))
+    );
+    assert_eq!(
+        board.parse_san("Zz9"),
+        Err(SanError::InvalidSyntax("Zz9".to_string()))
+    );
+    assert_eq!(
+        board.parse_san("O-O"),
+        Err(SanError::IllegalMove("O-O".to_string()))
+    );
+
+    let mut board = Board::new();
+    assert_eq!(board.parse_san("e4").unwrap().to_string(), "e2e4");
+    assert_eq!(board.parse_san("Nf3!?").unwrap().to_string(), "g1f3");
+}
+
+const PGN_GAMES: &str = r#"[Event "Casual \"blitz\""]
+[Site "?"]
+[Date "2024.01.02"]
+[Round "1"]
+[White "A"]
+[Black "B"]
+[Result "1-0"]
+
+1. e4 {best by test} e5 2. Nf3 $1 (2. f4 exf4 (2... d5) 3. Nf3) 2... Nc6
+; a rest of line comment
+3. Bc4 Nf6?? 4. Ng5 d5 5. exd5 Nxd5 6. Nxf7 Kxf7 7. Qf3+ Ke6 8. Nc3 Nb4
+9. O-O c6 10. d4 Qd6 1-0
+
+[Event "?"]
+[SetUp "1"]
+[FEN "6k1/5ppp/8/8/8/8/5PPP/R5K1 w - - 0 1"]
+
+1.Ra8# 1-0
+"#;
+
+#[test]
+fn pgn_read() {
+    let games = parse_pgn(PGN_GAMES).unwrap();
+    assert_eq!(games.len(), 2);
+    assert_eq!(games[0].tag("Event"), Some("Casual \"blitz\""));
+    assert_eq!(games[0].moves.len(), 20);
+    assert_eq!(games[0].result, GameResult::WhiteWins);
+    let mut board = games[1].board().unwrap();
+    assert_eq!(board.game_status(), GameStatus::Checkmate(PColor::White));
+}
+
+#[test]
+fn pgn_round_trip() {
+    let games = parse_pgn(PGN_GAMES).unwrap();
+    for game in &games {
+        let text = game.to_pgn().unwrap();
+        assert_eq!(parse_pgn(&text).unwrap(), vec![game.clone()]);
+    }
+    let text = games[0].to_pgn().unwrap();
+    assert!(text.starts_with("[Event \"Casual \\\"blitz\\\"\"]\n[Site \"?\"]\n[Date"));
+    assert!(text.contains("\n\n1. e4 e5 2. Nf3 Nc6 3. Bc4 Nf6 4. Ng5 d5"));
+    assert!(text.ends_with("10. d4 Qd6 1-0\n"));
+    assert!(text.lines().all(|line| line.len() < 80));
+
+    // A game from a position with Black to move numbers its first move "n..."
+    let board = parse_position(&["fen", "4k3/8/8/8/8/8/8/R3K3", "b", "-", "-", "0", "30"]).unwrap();
+    let mut game = PgnGame::from_board(&board);
+    game.moves.push(board.clone().parse_san("Kd7").unwrap());
+    assert!(game.to_pgn().unwrap().ends_with("\n30... Kd7 *\n"));
+}
+
+#[test]
+fn pgn_errors() {
+    let err = parse_pgn("1. e4 e5 2. Nf3 Nf6 3. Bb5 Bb4 4. Ke3 *").unwrap_err();
+    assert!(matches!(
+        err,
+        PgnError::IllegalMove {
+            game: 1,
+            ply: 7,
+            ..
+        }
+    ));
+    let err = parse_pgn("1. e4 e5 1-0\n\n1. d4 d5 2. Bd5 *").unwrap_err();
+    assert!(matches!(
+        err,
+        PgnError::IllegalMove {
+            game: 2,
+            ply: 3,
+            ..
+        }
+    ));
+    let err = parse_pgn("1. e4 (1. d4 d5 *").unwrap_err();
+    assert!(matches!(err, PgnError::Syntax { game: 1, .. }));
+    let err = parse_pgn("1. e4 {unclosed *").unwrap_err();
+    assert!(matches!(err, PgnError::Syntax { .. }));
+    let err = parse_pgn("1. e4 e5 1-0\n\n1. d4 d5 1-0\n\n1. c4 {unclosed *").unwrap_err();
+    assert_eq!(err.to_string(), "game 3: comment is never closed");
+}
+
+#[test]
+fn pgn_date_tag() {
+    assert_eq!(date_tag(UNIX_EPOCH), "1970.01.01");
+    let leap_day = UNIX_EPOCH + Duration::from_secs(11_016 * 86_400);
+    assert_eq!(date_tag(leap_day), "2000.02.29");
+}
+
+#[test]
+fn apply_move_checks_legality() {
+    let mut board = Board::new();
+    let mv = board.parse_san("e4").unwrap();
+    assert_eq!(board.apply_move(mv), Ok(()));
+    // The same move again has no pawn to move
+    assert_eq!(board.apply_move(mv), Err(IllegalMove(mv)));
+
+    // A pinned knight may not move, and the board stays as it was
+    let mut board = Board::from_fen("4k3/8/8/8/1b6/8/3N4/4K3 w - - 0 1").unwrap();
+    let fen = board.to_fen();
+    let pinned = Move {
+        from: (1, 3),
+        to: (3, 4),
+        piece: PieceType::Knight,
+        captured: None,
+        promotion: None,
+        flag: MoveFlag::Normal,
+    };
+    assert_eq!(board.apply_move(pinned), Err(IllegalMove(pinned)));
+    assert_eq!(board.to_fen(), fen);
+}
+
+#[test]
+fn move_list_navigation() {
+    let mut board = parse_position(&["startpos", "moves", "e2e4", "e7e5", "g1f3"]).unwrap();
+    let mut list = MoveList::new(&board);
+    assert_eq!(list.sans(), ["e4", "e5", "Nf3"]);
+    assert_eq!(list.rows(), vec![(1, Some(0), Some(1)), (2, Some(2), None)]);
+
+    // Stepping back keeps the later moves
+    list.go_to(&mut board, 1);
+    assert_eq!(board.history().len(), 1);
+    assert_eq!(list.len(), 3);
+    assert!(list.forward(&mut board));
+    assert_eq!(
+        board.to_fen(),
+        "rnbqkbnr/pppp1ppp/8/4p3/4P3/8/PPPP1PPP/RNBQKBNR w KQkq e6 0 2"
+    );
+
+    // A new move from an earlier position replaces them
+    let mv = board.parse_san("d4").unwrap();
+    list.play(&mut board, mv).unwrap();
+    assert_eq!(list.sans(), ["e4", "e5", "d4"]);
+    assert!(list.is_at_end());
+    // Playing it again is refused, like `Board::apply_move` refuses it
+    assert_eq!(list.play(&mut board, mv), Err(IllegalMove(mv)));
+    assert_eq!(list.sans(), ["e4", "e5", "d4"]);
+    assert_eq!(list.take_back(&mut board), Some(mv));
+    assert_eq!(list.len(), 2);
+
+    // Set up with Black to move, the first row has no White move
+    let board = parse_position(&[
+        "fen",
+        "4k3/8/8/8/8/8/8/R3K3",
+        "b",
+        "-",
+        "-",
+        "0",
+        "30",
+        "moves",
+        "e8d7",
+        "a1a7",
+    ])
+    .unwrap();
+    let list = MoveList::new(&board);
+    assert_eq!(list.rows(), vec![(30, None, Some(0)), (31, Some(1), None)]);
+    assert_eq!(list.sans(), ["Kd7", "Ra7+"]);
+}
+
+#[test]
+fn clock_time_controls() {
+    let secs = Duration::from_secs;
+    assert_eq!(
+        TimeControl::parse("3+2"),
+        Ok(TimeControl::Fischer {
+            base: secs(180),
+            increment: secs(2)
+        })
+    );
+    assert_eq!(
+        TimeControl::parse("5d3"),
+        Ok(TimeControl::Bronstein {
+            base: secs(300),
+            delay: secs(3)
+        })
+    );
+    assert_eq!(TimeControl::parse("0.5").unwrap().to_string(), "0.5");
+    assert!(TimeControl::parse("3+").is_err());
+    assert!(TimeControl::parse("blitz").is_err());
+    // Too long to be a duration at all
+    assert!(TimeControl::parse("inf").is_err());
+    assert!(TimeControl::parse("1e30").is_err());
+
+    // Fischer adds the increment whatever the move took
+    let start = Instant::now();
+    let mut clock = Clock::new(TimeControl::parse("1+2").unwrap());
+    clock.start(PColor::White, start);
+    clock.press(start + secs(10));
+    assert_eq!(clock.remaining(PColor::White, start + secs(20)), secs(52));
+    assert_eq!(clock.remaining(PColor::Black, start + secs(20)), secs(50));
+
+    // Bronstein gives back at most the delay
+    let mut clock = Clock::new(TimeControl::parse("1d5").unwrap());
+    clock.start(PColor::White, start);
+    clock.press(start + secs(3));
+    clock.press(start + secs(13));
+    assert_eq!(clock.remaining(PColor::White, start + secs(13)), secs(60));
+    assert_eq!(clock.remaining(PColor::Black, start + secs(13)), secs(55));
+
+    // White's flag falls, and stays down
+    assert_eq!(clock.flagged(start + secs(70)), None);
+    assert_eq!(clock.flagged(start + secs(75)), Some(PColor::White));
+    clock.press(start + secs(75));
+    assert!(clock.remaining(PColor::White, start + secs(75)).is_zero());
+
+    assert_eq!(format_clock(secs(185)), "3:05");
+    assert_eq!(format_clock(Duration::from_millis(9_450)), "0:09.4");
+}
+
+#[test]
+fn timeout_needs_mating_material() {
+    let board = Board::from_fen("4k3/8/8/8/8/8/4P3/4K3 w - - 0 1").unwrap();
+    assert!(board.has_mating_material(PColor::White));
+    assert!(!board.has_mating_material(PColor::Black));
+    let board = Board::from_fen("4k3/8/8/8/8/8/3NB3/4K3 w - - 0 1").unwrap();
+    assert!(board.has_mating_material(PColor::White));
+    let board = Board::from_fen("4k3/8/8/8/8/8/4N3/4K3 w - - 0 1").unwrap();
+    assert!(!board.has_mating_material(PColor::White));
+    // A knight or bishop can mate a king hemmed in by its own pawns
+    let board = Board::from_fen("4k3/4p3/8/8/8/8/4N3/4K3 w - - 0 1").unwrap();
+    assert!(board.has_mating_material(PColor::White));
+    let board = Board::from_fen("4k3/4p3/8/8/8/8/4B3/4K3 w - - 0 1").unwrap();
+    assert!(board.has_mating_material(PColor::White));
+    // Bishops all on squares of one colour never can
+    let board = Board::from_fen("4k3/8/8/8/8/8/3B1B2/4K3 w - - 0 1").unwrap();
+    assert!(!board.has_mating_material(PColor::White));
+    // nor can a bishop against a rook or queen, which cannot block for it
+    let board = Board::from_fen("4k3/8/8/8/8/8/4B3/3rK3 w - - 0 1").unwrap();
+    assert!(!board.has_mating_material(PColor::White));
+    let board = Board::from_fen("4k3/8/8/8/8/8/4B3/3qK3 w - - 0 1").unwrap();
+    assert!(!board.has_mating_material(PColor::White));
+
+    let start = Instant::now();
+    let control = TimeControl::SuddenDeath {
+        base: Duration::from_secs(60),
+    };
+    let mut game = Game::new(
+        Board::from_fen("4k3/8/8/8/8/8/4P3/4K3 b - - 0 1").unwrap(),
+        Some(control),
+    );
+    game.start_clock(start);
+    assert!(!game.check_flag(start + Duration::from_secs(30)));
+    assert!(game.check_flag(start + Duration::from_secs(61)));
+    assert_eq!(
+        game.outcome().map(|outcome| outcome.termination),
+        Some(Termination::Timeout)
+    );
+    assert_eq!(
+        game.outcome().and_then(|outcome| outcome.winner()),
+        Some(PColor::White)
+    );
+
+    let mut game = Game::new(
+        Board::from_fen("4k3/8/8/8/8/8/4P3/4K3 w - - 0 1").unwrap(),
+        Some(control),
+    );
+    game.start_clock(start);
+    assert!(game.check_flag(start + Duration::from_secs(61)));
+    assert_eq!(
+        game.outcome().map(|outcome| outcome.termination),
+        Some(Termination::TimeoutVsInsufficientMaterial)
+    );
+}
+
+fn play_sans(game: &mut Game, sans: &[&str]) {
+    for san in sans {
+        let mv = game.board().clone().parse_san(san).unwrap();
+        game.play(mv, Instant::now()).unwrap();
+    }
+}
+
+#[test]
+fn game_draws_and_resignation() {
+    let now = Instant::now();
+
+    // Threefold repetition is a draw only once claimed by the side to move
+    let mut game = Game::new(Board::new(), None);
+    play_sans(
+        &mut game,
+        &["Nf3", "Nf6", "Ng1", "Ng8", "Nf3", "Nf6", "Ng1"],
+    );
+    assert_eq!(game.claimable_draw(), None);
+    play_sans(&mut game, &["Ng8"]);
+    assert!(!game.is_over());
+    assert_eq!(
+        game.claim_draw(PColor::Black, now),
+        Err(GameError::NotYourTurn)
+    );
+    game.claim_draw(PColor::White, now).unwrap();
+    assert_eq!(
+        game.outcome().unwrap().termination,
+        Termination::ThreefoldRepetition
+    );
+    assert_eq!(game.take_back(now), Err(GameError::GameOver));
+
+    // An offer lapses when the opponent moves instead of accepting
+    let mut game = Game::new(Board::new(), None);
+    game.offer_draw(PColor::White).unwrap();
+    play_sans(&mut game, &["e4", "e5"]);
+    assert_eq!(game.draw_offer(), None);
+    assert_eq!(
+        game.accept_draw(PColor::Black, now),
+        Err(GameError::NoDrawOffered)
+    );
+    game.offer_draw(PColor::White).unwrap();
+    game.accept_draw(PColor::Black, now).unwrap();
+    assert_eq!(game.outcome().unwrap().result, GameResult::Draw);
+    let mv = game.board().clone().parse_san("Nf3").unwrap();
+    assert_eq!(game.play(mv, now), Err(GameError::GameOver));
+
+    let mut game = Game::new(Board::new(), Some(TimeControl::parse("3+2").unwrap()));
+    game.white = "Alice".to_string();
+    play_sans(&mut game, &["e4"]);
+    game.resign(PColor::Black, now).unwrap();
+    assert_eq!(
+        game.outcome().unwrap().to_string(),
+        "Black resigns, White wins"
+    );
+    let pgn = game.to_pgn();
+    assert_eq!(pgn.tag("White"), Some("Alice"));
+    assert_eq!(pgn.tag("Result"), Some("1-0"));
+    assert_eq!(pgn.tag("TimeControl"), Some("180+2"));
+    assert_eq!(pgn.tag("Termination"), Some("normal"));
+}
+
+#[test]
+fn game_take_back_after_mate() {
+    let now = Instant::now();
+    let mut game = Game::new(Board::new(), None);
+    assert_eq!(game.take_back(now), Err(GameError::NoMoveToTakeBack));
+    play_sans(&mut game, &["f3", "e5", "g4", "Qh4#"]);
+    assert_eq!(game.outcome().unwrap().winner(), Some(PColor::Black));
+
+    // Looking back at an earlier position keeps the game's side to move
+    game.go_to(1);
+    assert_eq!(game.turn(), PColor::White);
+
+    // A mate on the board can be taken back and the game goes on
+    game.take_back(now).unwrap();
+    assert!(!game.is_over());
+    assert_eq!(game.moves().len(), 3);
+    assert_eq!(game.turn(), PColor::Black);
+
+    // Moves are only played from the latest position
+    game.go_to(1);
+    let mv = game.board().clone().parse_san("d5").unwrap();
+    assert_eq!(game.play(mv, now), Err(GameError::NotAtLastMove));
+    assert_eq!(game.moves().len(), 3);
+}
+
+#[test]
+fn game_from_pgn() {
+    let pgn = r#"[Event "Club championship"]
+[White "Anna"]
+[Black "Ben"]
+[Result "1-0"]
+
+1. e4 e5 2. Qh5 Nc6 1-0"#;
+    let pgn = &parse_pgn(pgn).unwrap()[0];
+    let mut game = Game::from_pgn(pgn, None).unwrap();
+    assert_eq!(game.moves().len(), 4);
+    // Black resigned, so the game stays over
+    assert_eq!(game.outcome().unwrap().winner(), Some(PColor::White));
+    assert_eq!(game.outcome().unwrap().termination, Termination::Recorded);
+    let mv = game.board().clone().parse_san("Qxf7+").unwrap();
+    assert_eq!(game.play(mv, Instant::now()), Err(GameError::GameOver));
+
+    let saved = game.to_pgn();
+    assert_eq!(saved.tag("Event"), Some("Club championship"));
+    assert_eq!(saved.tag("White"), Some("Anna"));
+    assert_eq!(saved.tag("Black"), Some("Ben"));
+    assert_eq!(saved.result, GameResult::WhiteWins);
+    assert_eq!(saved.moves, pgn.moves);
+}
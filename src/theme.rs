@@ -0,0 +1,68 @@
+use sdl2::pixels::Color;
+
+/// The colours the window is drawn in. Highlights are drawn over the
+/// squares, so their alpha decides how much of the square shows through.
+#[derive(Copy, Clone, Debug)]
+pub struct Theme {
+    pub light_square: Color,
+    pub dark_square: Color,
+    pub selected: Color,
+    /// The from and to squares of the move just played.
+    pub last_move: Color,
+    /// Glow around a king in check.
+    pub check: Color,
+    /// Dots on empty target squares and rings around capturable pieces.
+    pub target: Color,
+    pub promotion_picker: Color,
+    /// Laid over the board once the game is over.
+    pub game_over: Color,
+    pub panel: Color,
+    pub move_number: Color,
+    pub move_text: Color,
+    pub current_move: Color,
+}
+
+/// Names accepted by `Theme::named`.
+pub const THEME_NAMES: [&str; 3] = ["brown", "green", "blue"];
+
+impl Theme {
+    /// One of the built-in themes, by name.
+    pub fn named(name: &str) -> Option<Theme> {
+        let theme = Theme::default();
+        match name {
+            "brown" => Some(theme),
+            "green" => Some(Theme {
+                light_square: Color::RGB(235, 236, 208),
+                dark_square: Color::RGB(115, 149, 82),
+                last_move: Color::RGBA(245, 246, 130, 150),
+                ..theme
+            }),
+            "blue" => Some(Theme {
+                light_square: Color::RGB(222, 227, 230),
+                dark_square: Color::RGB(120, 146, 176),
+                last_move: Color::RGBA(100, 180, 230, 130),
+                ..theme
+            }),
+            _ => None,
+        }
+    }
+}
+
+impl Default for Theme {
+    fn default() -> Theme {
+        Theme {
+            light_square: Color::RGB(198, 193, 170),
+            dark_square: Color::RGB(101, 97, 92),
+            selected: Color::RGBA(255, 255, 255, 90),
+            last_move: Color::RGBA(225, 200, 80, 120),
+            check: Color::RGBA(220, 30, 30, 60),
+            target: Color::RGBA(20, 20, 20, 70),
+            promotion_picker: Color::RGB(240, 240, 240),
+            game_over: Color::RGBA(0, 0, 0, 120),
+            panel: Color::RGB(48, 46, 43),
+            move_number: Color::RGB(140, 137, 130),
+            move_text: Color::RGB(230, 228, 222),
+            current_move: Color::RGB(92, 88, 80),
+        }
+    }
+}
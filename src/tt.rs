@@ -0,0 +1,83 @@
+use crate::chess::Move;
+
+/// Table size used unless asked otherwise.
+pub const DEFAULT_SIZE_MB: usize = 16;
+
+/// How a stored score relates to the real value of the position.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Bound {
+    /// The score is exact.
+    Exact,
+    /// The search failed high: the real score is at least this.
+    Lower,
+    /// No move raised alpha: the real score is at most this.
+    Upper,
+}
+
+/// What an earlier search found out about one position.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct TtEntry {
+    /// Full Zobrist hash, to tell positions sharing a slot apart.
+    pub key: u64,
+    pub depth: u32,
+    pub score: i32,
+    pub bound: Bound,
+    pub best_move: Option<Move>,
+}
+
+/// Fixed-size hash table of search results, indexed by Zobrist hash.
+pub struct TranspositionTable {
+    entries: Vec<Option<TtEntry>>,
+}
+
+impl Default for TranspositionTable {
+    fn default() -> Self {
+        Self::new(DEFAULT_SIZE_MB)
+    }
+}
+
+impl TranspositionTable {
+    /// A table taking up about `size_mb` megabytes, with at least one slot.
+    pub fn new(size_mb: usize) -> TranspositionTable {
+        let slots = size_mb * 1024 * 1024 / std::mem::size_of::<Option<TtEntry>>();
+        TranspositionTable {
+            entries: vec![None; slots.max(1)],
+        }
+    }
+
+    /// Number of entries the table can hold.
+    pub fn capacity(&self) -> usize {
+        self.entries.len()
+    }
+
+    fn slot(&self, key: u64) -> usize {
+        (key % self.entries.len() as u64) as usize
+    }
+
+    /// The entry for the position with hash `key`, if one is stored.
+    pub fn probe(&self, key: u64) -> Option<TtEntry> {
+        self.entries[self.slot(key)].filter(|entry| entry.key == key)
+    }
+
+    /// Stores `entry`, replacing whatever shares its slot unless that is
+    /// a deeper result for the same position.
+    pub fn store(&mut self, entry: TtEntry) {
+        let slot = self.slot(entry.key);
+        match self.entries[slot] {
+            Some(old) if old.key == entry.key && old.depth > entry.depth => {}
+            _ => self.entries[slot] = Some(entry),
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.iter_mut().for_each(|entry| *entry = None);
+    }
+
+    /// Used share of the table in permille, sampled from the first slots
+    /// as UCI's `hashfull` expects.
+    pub fn hashfull(&self) -> u32 {
+        let sample = self.entries.len().min(1000);
+        let used = self.entries[..sample].iter().flatten().count();
+        (used * 1000 / sample) as u32
+    }
+}
@@ -0,0 +1,271 @@
+use std::io::{self, BufRead};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use crate::chess::{Board, PColor};
+use crate::search::{SearchLimits, SearchResult, Searcher};
+use crate::tt::{TranspositionTable, DEFAULT_SIZE_MB};
+
+// Kept back from the clock for the GUI and the operating system
+const MOVE_OVERHEAD: Duration = Duration::from_millis(50);
+// Moves we expect still to play when the GUI does not send `movestogo`
+const DEFAULT_MOVES_TO_GO: u32 = 30;
+const MAX_HASH_MB: usize = 4096;
+
+/// Reads UCI commands from stdin until `quit` or the end of input.
+pub fn run() {
+    let mut uci = Uci::new();
+    for line in io::stdin().lock().lines() {
+        let Ok(line) = line else { break };
+        if !uci.handle(&line) {
+            break;
+        }
+    }
+    uci.stop();
+}
+
+/// State of a UCI session: the current position, the transposition table
+/// and a running search.
+pub struct Uci {
+    board: Board,
+    // Lent to the search thread while it runs
+    tt: Option<TranspositionTable>,
+    search: Option<(JoinHandle<TranspositionTable>, Arc<AtomicBool>)>,
+}
+
+impl Default for Uci {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Uci {
+    pub fn new() -> Uci {
+        Uci {
+            board: Board::new(),
+            tt: Some(TranspositionTable::default()),
+            search: None,
+        }
+    }
+
+    /// Handles one command line. Returns false on `quit`.
+    pub fn handle(&mut self, line: &str) -> bool {
+        let mut tokens = line.split_whitespace();
+        let Some(command) = tokens.next() else {
+            return true;
+        };
+        let args: Vec<&str> = tokens.collect();
+        match command {
+            "uci" => {
+                println!("id name ChessEngine");
+                println!("id author ChessEngine developers");
+                println!(
+                    "option name Hash type spin default {} min 1 max {}",
+                    DEFAULT_SIZE_MB, MAX_HASH_MB
+                );
+                println!("uciok");
+            }
+            "isready" => println!("readyok"),
+            "ucinewgame" => {
+                self.stop();
+                self.board = Board::new();
+                if let Some(tt) = &mut self.tt {
+                    tt.clear();
+                }
+            }
+            "position" => {
+                self.stop();
+                if let Err(err) = set_position(&mut self.board, &args) {
+                    println!("info string {}", err);
+                }
+            }
+            "go" => {
+                self.stop();
+                self.go(&args);
+            }
+            "stop" => self.stop(),
+            "setoption" => self.set_option(&args),
+            "quit" => return false,
+            // Unknown commands are ignored, as the protocol asks
+            _ => {}
+        }
+        true
+    }
+
+    fn set_option(&mut self, args: &[&str]) {
+        let (name, value) = parse_option(args);
+        match name.to_lowercase().as_str() {
+            "hash" => match value.and_then(|value| value.parse::<usize>().ok()) {
+                Some(size_mb) => {
+                    self.stop();
+                    self.tt = Some(TranspositionTable::new(size_mb.clamp(1, MAX_HASH_MB)));
+                }
+                None => println!("info string Hash needs a size in MB"),
+            },
+            _ => println!("info string unknown option '{}'", name),
+        }
+    }
+
+    fn go(&mut self, args: &[&str]) {
+        let params = GoParams::parse(args);
+        let limits = params.limits(self.board.turn);
+        let mut searcher = Searcher::new(limits);
+        searcher.tt = self.tt.take().unwrap_or_default();
+        let stop = searcher.stop.clone();
+        let thread_stop = stop.clone();
+        let mut board = self.board.clone();
+
+        let handle = thread::spawn(move || {
+            let start = Instant::now();
+            let result = searcher.search_with(&mut board, |result| {
+                println!("{}", info_line(result, start.elapsed()));
+            });
+            // In infinite mode the move may only be sent after `stop`
+            while params.infinite && !thread_stop.load(Ordering::Relaxed) {
+                thread::sleep(Duration::from_millis(1));
+            }
+            match result.best_move {
+                Some(mv) => println!("bestmove {}", mv),
+                None => println!("bestmove 0000"),
+            }
+            searcher.tt
+        });
+        self.search = Some((handle, stop));
+    }
+
+    // Ends a running search; it still prints its best move
+    fn stop(&mut self) {
+        if let Some((handle, stop)) = self.search.take() {
+            stop.store(true, Ordering::Relaxed);
+            self.tt = handle.join().ok();
+        }
+    }
+}
+
+/// Board for `position startpos|fen <FEN> [moves <move>...]`.
+pub fn parse_position(args: &[&str]) -> Result<Board, String> {
+    let mut board = Board::new();
+    set_position(&mut board, args)?;
+    Ok(board)
+}
+
+/// Sets `board` up as `parse_position` would. On an error nothing of the
+/// old position is kept: the board stops at the move before the illegal
+/// one, or is the start position if the FEN is bad.
+pub fn set_position(board: &mut Board, args: &[&str]) -> Result<(), String> {
+    *board = Board::new();
+    let moves_at = args.iter().position(|&arg| arg == "moves");
+    let (setup, moves) = match moves_at {
+        Some(index) => (&args[..index], &args[index + 1..]),
+        None => (args, &[][..]),
+    };
+
+    match setup.first() {
+        Some(&"startpos") => {}
+        Some(&"fen") => {
+            *board = Board::from_fen(&setup[1..].join(" ")).map_err(|err| err.to_string())?
+        }
+        _ => return Err("position needs 'startpos' or 'fen'".to_string()),
+    }
+
+    for name in moves {
+        let mv = board
+            .legal_moves()
+            .into_iter()
+            .find(|mv| mv.to_string() == *name)
+            .ok_or_else(|| format!("illegal move '{}'", name))?;
+        board.make_move(mv);
+    }
+    Ok(())
+}
+
+// Splits `name <name> [value <value>]`; both may contain spaces
+fn parse_option(args: &[&str]) -> (String, Option<String>) {
+    let value_at = args.iter().position(|&arg| arg == "value");
+    let name_end = value_at.unwrap_or(args.len());
+    let name_start = usize::from(args.first() == Some(&"name"));
+    let name = args[name_start.min(name_end)..name_end].join(" ");
+    let value = value_at.map(|index| args[index + 1..].join(" "));
+    (name, value)
+}
+
+/// Arguments of a `go` command.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct GoParams {
+    pub depth: Option<u32>,
+    pub movetime: Option<Duration>,
+    pub wtime: Option<Duration>,
+    pub btime: Option<Duration>,
+    pub winc: Option<Duration>,
+    pub binc: Option<Duration>,
+    pub movestogo: Option<u32>,
+    pub infinite: bool,
+}
+
+impl GoParams {
+    pub fn parse(args: &[&str]) -> GoParams {
+        let mut params = GoParams::default();
+        let mut iter = args.iter();
+        while let Some(&arg) = iter.next() {
+            let mut number = || iter.next().and_then(|value| value.parse::<u64>().ok());
+            let millis = |value: Option<u64>| value.map(Duration::from_millis);
+            match arg {
+                "depth" => params.depth = number().map(|depth| depth as u32),
+                "movetime" => params.movetime = millis(number()),
+                "wtime" => params.wtime = millis(number()),
+                "btime" => params.btime = millis(number()),
+                "winc" => params.winc = millis(number()),
+                "binc" => params.binc = millis(number()),
+                "movestogo" => params.movestogo = number().map(|moves| moves as u32),
+                "infinite" => params.infinite = true,
+                _ => {}
+            }
+        }
+        params
+    }
+
+    /// Search limits for `turn`. A clock is split evenly over the moves
+    /// still to play, plus most of the increment.
+    pub fn limits(&self, turn: PColor) -> SearchLimits {
+        let mut limits = SearchLimits {
+            depth: self.depth,
+            time: self.movetime,
+        };
+        if self.infinite {
+            return limits;
+        }
+        let (time, increment) = match turn {
+            PColor::White => (self.wtime, self.winc),
+            PColor::Black => (self.btime, self.binc),
+        };
+        if let (None, Some(time)) = (limits.time, time) {
+            let moves_to_go = self.movestogo.unwrap_or(DEFAULT_MOVES_TO_GO).max(1);
+            let share = time / moves_to_go + increment.unwrap_or_default() * 3 / 4;
+            let available = time.saturating_sub(MOVE_OVERHEAD);
+            limits.time = Some(share.min(available).max(Duration::from_millis(1)));
+        }
+        limits
+    }
+}
+
+/// `info` line for a finished iteration.
+pub fn info_line(result: &SearchResult, elapsed: Duration) -> String {
+    let score = match result.mate_in() {
+        Some(moves) => format!("mate {}", moves),
+        None => format!("cp {}", result.score),
+    };
+    let millis = elapsed.as_millis().max(1) as u64;
+    let pv: Vec<String> = result.pv.iter().map(|mv| mv.to_string()).collect();
+    format!(
+        "info depth {} score {} nodes {} nps {} time {} hashfull {} pv {}",
+        result.depth,
+        score,
+        result.nodes,
+        result.nodes * 1000 / millis,
+        millis,
+        result.hashfull,
+        pv.join(" ")
+    )
+}
@@ -0,0 +1,62 @@
+use crate::chess::{CastlingRights, PColor, Piece};
+
+// One key per piece on each square (2 colors * 6 types * 64 squares), then
+// the side to move, the four castling rights and the eight en passant files
+const PIECE_KEYS: usize = 2 * 6 * 64;
+const SIDE_INDEX: usize = PIECE_KEYS;
+const CASTLING_INDEX: usize = SIDE_INDEX + 1;
+const EN_PASSANT_INDEX: usize = CASTLING_INDEX + 4;
+const KEY_COUNT: usize = EN_PASSANT_INDEX + 8;
+
+// Fixed pseudo-random keys, so a position hashes the same in every run
+const KEYS: [u64; KEY_COUNT] = generate_keys(0x9E37_79B9_7F4A_7C15);
+
+// splitmix64, simple enough to run at compile time
+const fn generate_keys(seed: u64) -> [u64; KEY_COUNT] {
+    let mut keys = [0; KEY_COUNT];
+    let mut state = seed;
+    let mut i = 0;
+    while i < KEY_COUNT {
+        state = state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        keys[i] = z ^ (z >> 31);
+        i += 1;
+    }
+    keys
+}
+
+/// Key of `piece` standing on square `index`.
+pub fn piece_key(piece: Piece, index: usize) -> u64 {
+    KEYS[(piece.p_color as usize * 6 + piece.p_type as usize) * 64 + index]
+}
+
+/// Included in the hash when Black is to move.
+pub fn side_key() -> u64 {
+    KEYS[SIDE_INDEX]
+}
+
+/// Combined key of all castling rights still held.
+pub fn castling_key(rights: CastlingRights) -> u64 {
+    let mut key = 0;
+    for (index, allowed) in [
+        rights.kingside(PColor::White),
+        rights.queenside(PColor::White),
+        rights.kingside(PColor::Black),
+        rights.queenside(PColor::Black),
+    ]
+    .into_iter()
+    .enumerate()
+    {
+        if allowed {
+            key ^= KEYS[CASTLING_INDEX + index];
+        }
+    }
+    key
+}
+
+/// Key of an en passant capture being possible on `file`.
+pub fn en_passant_key(file: usize) -> u64 {
+    KEYS[EN_PASSANT_INDEX + file]
+}